@@ -1,6 +1,11 @@
-use std::{error::Error, fs::File, io::Write};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::Write,
+};
 
-use crate::{lexer, symbol, utils};
+use crate::{builtins, diagnostic::Diagnostic, lexer, symbol, utils};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum NodeType {
@@ -11,9 +16,45 @@ pub enum NodeType {
     AssignmentStatement,
     Expression,
     IfStatement,
+    ForStatement,
+    WhileStatement,
+    SwitchStatement,
+    Case,
+    Break,
+    Continue,
     Condition,
     FunctionDefinition,
     FunctionCall,
+    AnonymousFunction,
+    FunctionReference,
+    IndirectCall,
+    ParameterList,
+    Parameter,
+    TypeAnnotation,
+    ReturnStatement,
+    ListLiteral,
+    Element,
+    ListAppend,
+    ListLength,
+    MapLiteral,
+    MapEntry,
+    MapInsert,
+    Contains,
+    StructDefinition,
+    Field,
+    StructLiteral,
+    FieldValue,
+    FieldAccess,
+    FieldAssignment,
+    Negation,
+    Read,
+    FileRead,
+    FileWrite,
+    TryStatement,
+    Raise,
+    Assert,
+    Import,
+    QualifiedName,
     Term,
     Factor,
 }
@@ -24,6 +65,8 @@ pub struct Node {
     pub children: Vec<Box<Node>>,
     pub node_type: NodeType,
     pub data: String,
+    pub scope: Option<symbol::ScopeRef>,
+    pub type_: symbol::Type,
 }
 
 impl Node {
@@ -33,6 +76,8 @@ impl Node {
             children: Vec::new(),
             node_type,
             data: String::new(),
+            scope: None,
+            type_: symbol::Type::Unknown,
         }
     }
 
@@ -66,13 +111,11 @@ fn simplify_tree_aux(ast: &mut Box<Node>) {
             if ast.children.len() == 1 {
                 *ast = ast.children.pop().unwrap();
             }
-            if ast.children.len() == 2 {
-                if ast.children[1].node_type == NodeType::StatementList {
-                    let mut new_children = Vec::new();
-                    new_children.push(ast.children[0].clone());
-                    new_children.append(&mut ast.children[1].children);
-                    ast.children = new_children;
-                }
+            if ast.children.len() == 2 && ast.children[1].node_type == NodeType::StatementList {
+                let mut new_children = Vec::new();
+                new_children.push(ast.children[0].clone());
+                new_children.append(&mut ast.children[1].children);
+                ast.children = new_children;
             }
         }
         NodeType::Statement => {
@@ -88,9 +131,45 @@ fn simplify_tree_aux(ast: &mut Box<Node>) {
         }
         NodeType::AssignmentStatement => {}
         NodeType::IfStatement => {}
+        NodeType::ForStatement => {}
+        NodeType::WhileStatement => {}
+        NodeType::SwitchStatement => {}
+        NodeType::Case => {}
+        NodeType::Break => {}
+        NodeType::Continue => {}
         NodeType::Condition => {}
         NodeType::FunctionDefinition => {}
         NodeType::FunctionCall => {}
+        NodeType::AnonymousFunction => {}
+        NodeType::FunctionReference => {}
+        NodeType::IndirectCall => {}
+        NodeType::ParameterList => {}
+        NodeType::Parameter => {}
+        NodeType::TypeAnnotation => {}
+        NodeType::ReturnStatement => {}
+        NodeType::ListLiteral => {}
+        NodeType::Element => {}
+        NodeType::ListAppend => {}
+        NodeType::ListLength => {}
+        NodeType::MapLiteral => {}
+        NodeType::MapEntry => {}
+        NodeType::MapInsert => {}
+        NodeType::Contains => {}
+        NodeType::StructDefinition => {}
+        NodeType::Field => {}
+        NodeType::StructLiteral => {}
+        NodeType::FieldValue => {}
+        NodeType::FieldAccess => {}
+        NodeType::FieldAssignment => {}
+        NodeType::Negation => {}
+        NodeType::Read => {}
+        NodeType::FileRead => {}
+        NodeType::FileWrite => {}
+        NodeType::TryStatement => {}
+        NodeType::Raise => {}
+        NodeType::Assert => {}
+        NodeType::Import => {}
+        NodeType::QualifiedName => {}
         NodeType::Term => {
             ast.node_type = NodeType::Expression;
             if ast.children.len() == 1 {
@@ -109,16 +188,13 @@ fn simplify_tree_aux(ast: &mut Box<Node>) {
 pub fn fill_string_list(ast: &mut Box<Node>, string_list: &mut symbol::StringList) {
     if let NodeType::Expression = ast.node_type {
         if let Some(token) = &ast.token {
-            match token.token_type() {
-                lexer::TokenType::StringLiteral(value) => {
-                    let index = string_list.add(value);
-                    ast.token = Some(lexer::Token::new(
-                        lexer::TokenType::StringListIndex(index),
-                        token.line(),
-                        token.column(),
-                    ));
-                }
-                _ => {}
+            if let lexer::TokenType::StringLiteral(value) = token.token_type() {
+                let index = string_list.add(value);
+                ast.token = Some(lexer::Token::new(
+                    lexer::TokenType::StringListIndex(index),
+                    token.line(),
+                    token.column(),
+                ));
             }
         }
     }
@@ -128,82 +204,880 @@ pub fn fill_string_list(ast: &mut Box<Node>, string_list: &mut symbol::StringLis
     }
 }
 
-pub fn find_symbols(ast: &mut Box<Node>, symbol_table: &mut symbol::SymbolTable) {
-    if let NodeType::AssignmentStatement = ast.node_type {
-        if let Some(token) = &ast.children[0].token {
-            match token.token_type() {
-                lexer::TokenType::Identifier(name) => {
-                    let symbol = symbol_table.add(name, symbol::SymbolKind::Variable);
+// A value written out in the source, such as a case of `velg`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(usize),
+}
+
+// The literal `node` is, with `minus` in front of a number included. Strings
+// are known by their index in the string list.
+pub fn literal(node: &Node) -> Option<Literal> {
+    if node.node_type == NodeType::Negation {
+        return match literal(&node.children[0])? {
+            Literal::Int(value) => value.checked_neg().map(Literal::Int),
+            Literal::Float(value) => Some(Literal::Float(-value)),
+            _ => None,
+        };
+    }
+    if !node.children.is_empty() {
+        return None;
+    }
+    match node.token.as_ref()?.token_type() {
+        lexer::TokenType::IntLiteral(value) => Some(Literal::Int(*value)),
+        lexer::TokenType::FloatLiteral(value) => Some(Literal::Float(*value)),
+        lexer::TokenType::BoolLiteral(value) => Some(Literal::Bool(*value)),
+        lexer::TokenType::StringListIndex(index) => Some(Literal::String(*index)),
+        _ => None,
+    }
+}
+
+pub fn find_symbols(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let global = symbol_table.global_scope();
+    builtins::declare(symbol_table);
+    collect_declarations(ast, symbol_table, global, &mut diagnostics);
+    resolve_symbols(ast, symbol_table, global, &mut diagnostics);
+    check_global_reads(ast, symbol_table, &mut diagnostics);
+    diagnostics
+}
+
+// Creates the scope tree and registers every mechanism up front, so that
+// `utfør` may refer to mechanisms defined further down in the file. The scope
+// of each mechanism body and block is stored on its node for the next pass.
+fn collect_declarations(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match ast.node_type {
+        NodeType::FunctionDefinition => {
+            if let Some(token) = ast.children[0].token.clone() {
+                if let lexer::TokenType::Identifier(name) = token.token_type() {
+                    if symbol_table.lookup_in_scope(scope, name).is_some() {
+                        diagnostics.push(Diagnostic::at_token(
+                            format!("mekanismen `{}` er allerede definert", name),
+                            &token,
+                            name.chars().count(),
+                        ));
+                    }
+                    let symbol = symbol_table.declare(scope, name, symbol::SymbolKind::Function);
                     ast.children[0].token = Some(lexer::Token::new(
                         lexer::TokenType::Symbol(symbol),
                         token.line(),
                         token.column(),
                     ));
-                    for child in &mut ast.children[1].children {
-                        find_symbols(child, symbol_table);
-                    }
-                    return;
                 }
-                _ => {}
+            }
+            let body_scope = symbol_table.add_scope(symbol::ScopeKind::Mechanism, scope);
+            ast.scope = Some(body_scope);
+            for child in ast.children.iter_mut().skip(1) {
+                collect_declarations(child, symbol_table, body_scope, diagnostics);
+            }
+        }
+        // The mechanism has no name to be found by, so its symbol is kept in
+        // its own scope under one that cannot be written.
+        NodeType::AnonymousFunction => {
+            let token = ast.token.clone().unwrap();
+            let body_scope = symbol_table.add_scope(symbol::ScopeKind::Mechanism, scope);
+            let name = format!("mekanisme på linje {}", token.line());
+            let symbol = symbol_table.declare(body_scope, &name, symbol::SymbolKind::Function);
+            ast.token = Some(lexer::Token::new(
+                lexer::TokenType::Symbol(symbol),
+                token.line(),
+                token.column(),
+            ));
+            ast.scope = Some(body_scope);
+            for child in &mut ast.children {
+                collect_declarations(child, symbol_table, body_scope, diagnostics);
+            }
+        }
+        NodeType::IfStatement => {
+            collect_declarations(&mut ast.children[0], symbol_table, scope, diagnostics);
+            for body in ast.children.iter_mut().skip(1) {
+                let body_scope = symbol_table.add_scope(symbol::ScopeKind::Block, scope);
+                body.scope = Some(body_scope);
+                collect_declarations(body, symbol_table, body_scope, diagnostics);
+            }
+        }
+        NodeType::StructDefinition => declare_struct(ast, symbol_table, scope, diagnostics),
+        NodeType::Import => declare_module(ast, symbol_table, scope, diagnostics),
+        NodeType::TryStatement => {
+            for body in ast.children.iter_mut().take(2) {
+                let body_scope = symbol_table.add_scope(symbol::ScopeKind::Block, scope);
+                body.scope = Some(body_scope);
+                collect_declarations(body, symbol_table, body_scope, diagnostics);
+            }
+        }
+        // The body of a case is the last child, after the value if it has one.
+        NodeType::Case => {
+            let (body, values) = ast.children.split_last_mut().unwrap();
+            for value in values {
+                collect_declarations(value, symbol_table, scope, diagnostics);
+            }
+            let body_scope = symbol_table.add_scope(symbol::ScopeKind::Block, scope);
+            body.scope = Some(body_scope);
+            collect_declarations(body, symbol_table, body_scope, diagnostics);
+        }
+        NodeType::WhileStatement => {
+            collect_declarations(&mut ast.children[0], symbol_table, scope, diagnostics);
+            let body_scope = symbol_table.add_scope(symbol::ScopeKind::Loop, scope);
+            ast.children[1].scope = Some(body_scope);
+            collect_declarations(&mut ast.children[1], symbol_table, body_scope, diagnostics);
+        }
+        NodeType::ForStatement => {
+            collect_declarations(&mut ast.children[1], symbol_table, scope, diagnostics);
+            let body_scope = symbol_table.add_scope(symbol::ScopeKind::Loop, scope);
+            ast.children[2].scope = Some(body_scope);
+            collect_declarations(&mut ast.children[2], symbol_table, body_scope, diagnostics);
+        }
+        _ => {
+            for child in &mut ast.children {
+                collect_declarations(child, symbol_table, scope, diagnostics);
             }
         }
     }
-    if let NodeType::FunctionDefinition = ast.node_type {
-        if let Some(token) = &ast.children[0].token {
-            match token.token_type() {
-                lexer::TokenType::Identifier(name) => {
-                    let symbol = symbol_table.add(name, symbol::SymbolKind::Function);
-                    ast.children[0].token = Some(lexer::Token::new(
+}
+
+// Walks the tree in source order, declaring variables at their first `la` and
+// replacing every identifier with a reference into the symbol table.
+fn resolve_symbols(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let scope = ast.scope.unwrap_or(scope);
+    match ast.node_type {
+        NodeType::AssignmentStatement => {
+            resolve_symbols(&mut ast.children[1], symbol_table, scope, diagnostics);
+            let constant = ast.data == "alltid";
+            declare_variable(
+                &mut ast.children[0],
+                constant,
+                symbol_table,
+                scope,
+                diagnostics,
+            );
+        }
+        NodeType::FunctionDefinition => {
+            let parameters =
+                declare_parameters(&mut ast.children[1], symbol_table, scope, diagnostics);
+            if let Some(lexer::TokenType::Symbol(symbol)) = ast.children[0]
+                .token
+                .as_ref()
+                .map(|token| token.token_type())
+            {
+                symbol_table.set_parameters(*symbol, parameters);
+            }
+            resolve_symbols(&mut ast.children[1], symbol_table, scope, diagnostics);
+            resolve_symbols(&mut ast.children[2], symbol_table, scope, diagnostics);
+        }
+        // The element variable is new on every loop and only visible in the
+        // body, so it may shadow a variable of the same name outside.
+        NodeType::ForStatement => {
+            resolve_symbols(&mut ast.children[1], symbol_table, scope, diagnostics);
+            let body_scope = ast.children[2].scope.unwrap();
+            let identifier = &mut ast.children[0];
+            if let Some(token) = identifier.token.clone() {
+                if let lexer::TokenType::Identifier(name) = token.token_type() {
+                    let symbol =
+                        symbol_table.declare(body_scope, name, symbol::SymbolKind::Variable);
+                    identifier.token = Some(lexer::Token::new(
                         lexer::TokenType::Symbol(symbol),
                         token.line(),
                         token.column(),
                     ));
-                    for child in &mut ast.children[1].children {
-                        find_symbols(child, symbol_table);
-                    }
-                    return;
                 }
-                _ => {}
             }
+            resolve_symbols(&mut ast.children[2], symbol_table, body_scope, diagnostics);
         }
-    }
-    if let NodeType::FunctionCall = ast.node_type {
-        if let Some(token) = &ast.children[0].token {
-            match token.token_type() {
-                lexer::TokenType::Identifier(name) => {
-                    let symbol = symbol_table
-                        .get_symbol_ref(name)
-                        .expect("Function not found");
-                    ast.children[0].token = Some(lexer::Token::new(
-                        lexer::TokenType::Symbol(symbol),
-                        token.line(),
-                        token.column(),
-                    ));
-                    return;
+        // The message of the error is only visible in the handler, like the
+        // element variable of a loop.
+        NodeType::TryStatement => {
+            resolve_symbols(&mut ast.children[0], symbol_table, scope, diagnostics);
+            let handler_scope = ast.children[1].scope.unwrap();
+            if let Some(identifier) = ast.children.get_mut(2) {
+                if let Some(token) = identifier.token.clone() {
+                    if let lexer::TokenType::Identifier(name) = token.token_type() {
+                        let symbol =
+                            symbol_table.declare(handler_scope, name, symbol::SymbolKind::Variable);
+                        identifier.token = Some(lexer::Token::new(
+                            lexer::TokenType::Symbol(symbol),
+                            token.line(),
+                            token.column(),
+                        ));
+                    }
                 }
-                _ => {}
             }
+            resolve_symbols(
+                &mut ast.children[1],
+                symbol_table,
+                handler_scope,
+                diagnostics,
+            );
         }
-    }
-
-    match ast.token.clone() {
-        Some(token) => match token.token_type() {
-            lexer::TokenType::Identifier(name) => {
-                let symbol = symbol_table.get_symbol_ref(name).expect("Symbol not found");
+        NodeType::StructDefinition => {
+            for field in ast.children.iter_mut().skip(1) {
+                resolve_symbols(field, symbol_table, scope, diagnostics);
+            }
+        }
+        // A type is either one of the built-in ones, left as it is, or the
+        // name of a structure.
+        NodeType::TypeAnnotation => {
+            if let Some(structure) =
+                symbol_table.lookup(scope, &ast.data, &symbol::SymbolKind::Struct)
+            {
+                let token = ast.token.clone().unwrap();
                 ast.token = Some(lexer::Token::new(
-                    lexer::TokenType::Symbol(symbol),
+                    lexer::TokenType::Symbol(structure),
                     token.line(),
                     token.column(),
                 ));
+            }
+        }
+        NodeType::StructLiteral => {
+            resolve_struct_literal(ast, symbol_table, scope, diagnostics);
+        }
+        // The field can only be found once the type of the structure is
+        // known, which is left to the type checker.
+        NodeType::FieldAccess => {
+            resolve_symbols(&mut ast.children[1], symbol_table, scope, diagnostics);
+        }
+        NodeType::FunctionCall => {
+            if ast.children[0].node_type == NodeType::QualifiedName {
+                resolve_qualified_name(&mut ast.children[0], symbol_table, scope, diagnostics);
+            } else {
+                resolve_reference(
+                    &mut ast.children[0],
+                    symbol::SymbolKind::Function,
+                    symbol_table,
+                    scope,
+                    diagnostics,
+                );
+                // A variable holding a mechanism.
+                if let Some(lexer::TokenType::Symbol(symbol)) = ast.children[0]
+                    .token
+                    .as_ref()
+                    .map(|token| token.token_type())
+                {
+                    if symbol_table.get(*symbol).kind != symbol::SymbolKind::Function {
+                        ast.node_type = NodeType::IndirectCall;
+                    }
+                }
+            }
+            for child in ast.children.iter_mut().skip(1) {
+                resolve_symbols(child, symbol_table, scope, diagnostics);
+            }
+        }
+        NodeType::AnonymousFunction => {
+            let parameters =
+                declare_parameters(&mut ast.children[0], symbol_table, scope, diagnostics);
+            for child in &mut ast.children {
+                resolve_symbols(child, symbol_table, scope, diagnostics);
+            }
+            if let Some(lexer::TokenType::Symbol(symbol)) =
+                ast.token.as_ref().map(|token| token.token_type())
+            {
+                let mut captures = Vec::new();
+                find_captures(&ast.children[1], symbol_table, scope, &mut captures);
+                symbol_table.set_parameters(*symbol, parameters);
+                symbol_table.set_captures(*symbol, captures);
+            }
+        }
+        // The token is the word `skriv`, which is not a keyword and would
+        // otherwise be looked up as a variable.
+        NodeType::FileWrite => {
+            for child in &mut ast.children {
+                resolve_symbols(child, symbol_table, scope, diagnostics);
+            }
+        }
+        _ => {
+            resolve_reference(
+                ast,
+                symbol::SymbolKind::Variable,
+                symbol_table,
+                scope,
+                diagnostics,
+            );
+            for child in &mut ast.children {
+                resolve_symbols(child, symbol_table, scope, diagnostics);
+            }
+        }
+    }
+}
+
+// The globals a mechanism reads and the mechanisms it calls by name.
+#[derive(Default)]
+struct Uses {
+    globals: Vec<symbol::SymbolRef>,
+    mechanisms: Vec<symbol::SymbolRef>,
+}
+
+// A mechanism may read a global that is given its value further down, but must
+// not be called before that, directly or through the mechanisms it calls.
+// Calls through a variable are not followed here and are left to the
+// simulator.
+fn check_global_reads(
+    ast: &Node,
+    symbol_table: &symbol::SymbolTable,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut uses = HashMap::new();
+    collect_uses(ast, symbol_table, &mut uses);
+    let mut given = HashSet::new();
+    check_calls(ast, symbol_table, &uses, &mut given, diagnostics);
+}
+
+fn symbol_of(node: &Node) -> Option<symbol::SymbolRef> {
+    match node.token.as_ref().map(|token| token.token_type()) {
+        Some(lexer::TokenType::Symbol(symbol)) => Some(*symbol),
+        _ => None,
+    }
+}
+
+fn collect_uses(
+    ast: &Node,
+    symbol_table: &symbol::SymbolTable,
+    uses: &mut HashMap<symbol::SymbolRef, Uses>,
+) {
+    if ast.node_type == NodeType::FunctionDefinition {
+        if let Some(mechanism) = symbol_of(&ast.children[0]) {
+            let mut found = Uses::default();
+            for child in ast.children.iter().skip(1) {
+                find_uses(child, symbol_table, &mut found);
+            }
+            uses.insert(mechanism, found);
+        }
+    }
+    for child in &ast.children {
+        collect_uses(child, symbol_table, uses);
+    }
+}
+
+// Anonymous mechanisms are left out, as they only read when they are called.
+fn find_uses(ast: &Node, symbol_table: &symbol::SymbolTable, found: &mut Uses) {
+    match ast.node_type {
+        NodeType::FunctionDefinition | NodeType::AnonymousFunction => return,
+        NodeType::AssignmentStatement => {
+            find_uses(&ast.children[1], symbol_table, found);
+            return;
+        }
+        NodeType::FunctionCall => {
+            if let Some(mechanism) = symbol_of(&ast.children[0]) {
+                if !found.mechanisms.contains(&mechanism) {
+                    found.mechanisms.push(mechanism);
+                }
+            }
+        }
+        _ => {
+            if let Some(variable) = symbol_of(ast) {
+                let symbol = symbol_table.get(variable);
+                if symbol.kind.satisfies(&symbol::SymbolKind::Variable)
+                    && symbol_table.is_global(variable)
+                    && !found.globals.contains(&variable)
+                {
+                    found.globals.push(variable);
+                }
+            }
+        }
+    }
+    for child in &ast.children {
+        find_uses(child, symbol_table, found);
+    }
+}
+
+// Walks the program in the order it runs, outside of mechanisms, keeping the
+// globals given a value so far.
+fn check_calls(
+    ast: &Node,
+    symbol_table: &symbol::SymbolTable,
+    uses: &HashMap<symbol::SymbolRef, Uses>,
+    given: &mut HashSet<symbol::SymbolRef>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match ast.node_type {
+        NodeType::FunctionDefinition | NodeType::AnonymousFunction | NodeType::Import => {}
+        NodeType::AssignmentStatement => {
+            check_calls(&ast.children[1], symbol_table, uses, given, diagnostics);
+            if let Some(variable) = symbol_of(&ast.children[0]) {
+                given.insert(variable);
+            }
+        }
+        NodeType::FunctionCall => {
+            for child in ast.children.iter().skip(1) {
+                check_calls(child, symbol_table, uses, given, diagnostics);
+            }
+            let Some(mechanism) = symbol_of(&ast.children[0]) else {
                 return;
+            };
+            let mut seen = vec![mechanism];
+            let mut index = 0;
+            while index < seen.len() {
+                let Some(found) = uses.get(&seen[index]) else {
+                    index += 1;
+                    continue;
+                };
+                if let Some(global) = found.globals.iter().find(|global| !given.contains(global)) {
+                    let token = ast.children[0].token.as_ref().unwrap();
+                    let name = &symbol_table.get(mechanism).name;
+                    let global = &symbol_table.get(*global).name;
+                    diagnostics.push(
+                        Diagnostic::at_token(
+                            format!("`{}` leser `{}` før den har fått en verdi", name, global),
+                            token,
+                            name.chars().count(),
+                        )
+                        .with_help(format!(
+                            "gi `{}` en verdi før `{}` blir utført",
+                            global, name
+                        )),
+                    );
+                    return;
+                }
+                for called in &found.mechanisms {
+                    if !seen.contains(called) {
+                        seen.push(*called);
+                    }
+                }
+                index += 1;
             }
-            _ => {}
-        },
-        None => {}
+        }
+        _ => {
+            for child in &ast.children {
+                check_calls(child, symbol_table, uses, given, diagnostics);
+            }
+        }
     }
+}
 
-    for child in &mut ast.children {
-        find_symbols(child, symbol_table);
+// Declares the structure and its fields, each also under its definite forms,
+// in a scope of their own.
+fn declare_struct(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(token) = ast.children[0].token.clone() else {
+        return;
+    };
+    let lexer::TokenType::Identifier(name) = token.token_type() else {
+        return;
+    };
+    if symbol_table.lookup_in_scope(scope, name).is_some() {
+        diagnostics.push(Diagnostic::at_token(
+            format!("`{}` er allerede definert", name),
+            &token,
+            name.chars().count(),
+        ));
+    }
+    let symbol = symbol_table.declare(scope, name, symbol::SymbolKind::Struct);
+    ast.children[0].token = Some(lexer::Token::new(
+        lexer::TokenType::Symbol(symbol),
+        token.line(),
+        token.column(),
+    ));
+
+    let struct_scope = symbol_table.add_scope(symbol::ScopeKind::Struct, scope);
+    let mut fields = Vec::new();
+    for field in ast.children.iter_mut().skip(1) {
+        let identifier = &mut field.children[0];
+        let Some(token) = identifier.token.clone() else {
+            continue;
+        };
+        let lexer::TokenType::Identifier(name) = token.token_type() else {
+            continue;
+        };
+        if symbol_table.lookup_in_scope(struct_scope, name).is_some() {
+            diagnostics.push(Diagnostic::at_token(
+                format!("feltet `{}` er allerede definert", name),
+                &token,
+                name.chars().count(),
+            ));
+        }
+        let field_symbol = symbol_table.declare(struct_scope, name, symbol::SymbolKind::Field);
+        for definite_form in utils::definite_forms(name) {
+            symbol_table.alias(struct_scope, &definite_form, field_symbol);
+        }
+        identifier.token = Some(lexer::Token::new(
+            lexer::TokenType::Symbol(field_symbol),
+            token.line(),
+            token.column(),
+        ));
+        fields.push(field_symbol);
+    }
+    symbol_table.set_fields(symbol, fields);
+}
+
+// A module gets a scope of its own under the global one, so its mechanisms
+// see the built-in ones but not those of the file using it, and are only
+// reached through the name of the module. A file used a second time has no
+// tree of its own, it shares the scope made the first time.
+fn declare_module(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let module_scope = match symbol_table.module(&ast.data) {
+        Some(module_scope) => module_scope,
+        None => {
+            let module_scope = symbol_table.add_module(&ast.data);
+            for child in &mut ast.children {
+                collect_declarations(child, symbol_table, module_scope, diagnostics);
+            }
+            module_scope
+        }
+    };
+    ast.scope = Some(module_scope);
+
+    let token = ast.token.clone().unwrap();
+    let name = module_name(&ast.data);
+    if symbol_table.lookup_in_scope(scope, &name).is_some() {
+        diagnostics.push(Diagnostic::at_token(
+            format!("`{}` er allerede definert", name),
+            &token,
+            4,
+        ));
+        return;
+    }
+    let symbol = symbol_table.declare(scope, &name, symbol::SymbolKind::Module);
+    symbol_table.set_members(symbol, module_scope);
+}
+
+// A module is named after its file, so `bruk "lib/matte.nb"` gives `matte`.
+fn module_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Resolves `kvadrat fra matte` to the mechanism in the module, leaving just
+// the name in its place so the call looks like any other from here on.
+fn resolve_qualified_name(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let module = ast.children.pop().unwrap();
+    let mut name = ast.children.pop().unwrap();
+    let (Some(module_token), Some(name_token)) = (module.token, name.token.clone()) else {
+        return;
+    };
+    let (lexer::TokenType::Identifier(module_name), lexer::TokenType::Identifier(mechanism_name)) =
+        (module_token.token_type(), name_token.token_type())
+    else {
+        return;
+    };
+    let Some(module) = symbol_table.lookup(scope, module_name, &symbol::SymbolKind::Module) else {
+        diagnostics.push(Diagnostic::at_token(
+            format!("modulen `{}` er ikke brukt", module_name),
+            &module_token,
+            module_name.chars().count(),
+        ));
+        *ast = name;
+        return;
+    };
+    let members = symbol_table.get(module).members.unwrap();
+    match symbol_table
+        .lookup_in_scope(members, mechanism_name)
+        .filter(|symbol| symbol_table.get(*symbol).kind == symbol::SymbolKind::Function)
+    {
+        Some(symbol) => {
+            name.token = Some(lexer::Token::new(
+                lexer::TokenType::Symbol(symbol),
+                name_token.line(),
+                name_token.column(),
+            ));
+        }
+        None => {
+            let mut diagnostic = Diagnostic::at_token(
+                format!(
+                    "modulen `{}` har ingen mekanisme `{}`",
+                    module_name, mechanism_name
+                ),
+                &name_token,
+                mechanism_name.chars().count(),
+            );
+            if let Some(suggestion) = symbol_table
+                .closest_name(members, mechanism_name, &symbol::SymbolKind::Function)
+                .filter(|suggestion| symbol_table.lookup_in_scope(members, suggestion).is_some())
+            {
+                diagnostic = diagnostic.with_help(format!("mente du `{}`?", suggestion));
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+    *ast = name;
+}
+
+// Resolves `ny Person med navn "Ola" og alder 30`, where every field of the
+// structure must be given exactly once.
+fn resolve_struct_literal(
+    ast: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    resolve_reference(
+        &mut ast.children[0],
+        symbol::SymbolKind::Struct,
+        symbol_table,
+        scope,
+        diagnostics,
+    );
+    for field_value in ast.children.iter_mut().skip(1) {
+        resolve_symbols(
+            &mut field_value.children[1],
+            symbol_table,
+            scope,
+            diagnostics,
+        );
+    }
+    let Some(struct_token) = ast.children[0].token.clone() else {
+        return;
+    };
+    let lexer::TokenType::Symbol(struct_ref) = struct_token.token_type() else {
+        return;
+    };
+    let structure = symbol_table.get(*struct_ref).clone();
+
+    let mut given = Vec::new();
+    for field_value in ast.children.iter_mut().skip(1) {
+        let identifier = &mut field_value.children[0];
+        let Some(token) = identifier.token.clone() else {
+            continue;
+        };
+        let lexer::TokenType::Identifier(name) = token.token_type() else {
+            continue;
+        };
+        let field = symbol_table
+            .lookup_field(*struct_ref, name)
+            .filter(|field| symbol_table.get(*field).name == *name);
+        let Some(field) = field else {
+            let mut diagnostic = Diagnostic::at_token(
+                format!("`{}` har ikke feltet `{}`", structure.name, name),
+                &token,
+                name.chars().count(),
+            );
+            if let Some(suggestion) = symbol_table.closest_field(*struct_ref, name) {
+                diagnostic = diagnostic.with_help(format!("mente du `{}`?", suggestion));
+            }
+            diagnostics.push(diagnostic);
+            continue;
+        };
+        if given.contains(&field) {
+            diagnostics.push(Diagnostic::at_token(
+                format!("feltet `{}` er gitt mer enn én gang", name),
+                &token,
+                name.chars().count(),
+            ));
+        }
+        given.push(field);
+        identifier.token = Some(lexer::Token::new(
+            lexer::TokenType::Symbol(field),
+            token.line(),
+            token.column(),
+        ));
+    }
+
+    let missing: Vec<String> = structure
+        .fields
+        .iter()
+        .filter(|field| !given.contains(field))
+        .map(|field| format!("`{}`", symbol_table.get(*field).name))
+        .collect();
+    if !missing.is_empty() {
+        diagnostics.push(Diagnostic::at_token(
+            format!(
+                "`{}` mangler verdi for {}",
+                structure.name,
+                missing.join(", ")
+            ),
+            &struct_token,
+            structure.name.chars().count(),
+        ));
+    }
+}
+
+fn declare_parameters(
+    parameter_list: &mut Box<Node>,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<symbol::SymbolRef> {
+    let mut parameters = Vec::new();
+    for parameter in &mut parameter_list.children {
+        let identifier = &mut parameter.children[0];
+        let Some(token) = identifier.token.clone() else {
+            continue;
+        };
+        let lexer::TokenType::Identifier(name) = token.token_type() else {
+            continue;
+        };
+        if symbol_table.lookup_in_scope(scope, name).is_some() {
+            diagnostics.push(Diagnostic::at_token(
+                format!("parameteren `{}` er allerede definert", name),
+                &token,
+                name.chars().count(),
+            ));
+        }
+        let symbol = symbol_table.declare(scope, name, symbol::SymbolKind::Variable);
+        identifier.token = Some(lexer::Token::new(
+            lexer::TokenType::Symbol(symbol),
+            token.line(),
+            token.column(),
+        ));
+        parameters.push(symbol);
+    }
+    parameters
+}
+
+// A constant is always a new binding in the current scope, while a variable
+// reuses one visible under the rules of `lookup_assignable`, unless that one is
+// a constant or a mechanism.
+fn declare_variable(
+    identifier: &mut Box<Node>,
+    constant: bool,
+    symbol_table: &mut symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(token) = identifier.token.clone() else {
+        return;
+    };
+    let lexer::TokenType::Identifier(name) = token.token_type() else {
+        return;
+    };
+    let existing = match constant {
+        true => symbol_table.lookup_in_scope(scope, name),
+        false => symbol_table.lookup_assignable(scope, name),
+    };
+    let message = match existing.map(|symbol| &symbol_table.get(symbol).kind) {
+        Some(symbol::SymbolKind::Function) => Some(format!(
+            "`{}` er en mekanisme og kan ikke få en verdi",
+            name
+        )),
+        Some(symbol::SymbolKind::Constant) => Some(format!(
+            "`{}` er en konstant og kan ikke få en ny verdi",
+            name
+        )),
+        Some(symbol::SymbolKind::Struct) => {
+            Some(format!("`{}` er en struktur og kan ikke få en verdi", name))
+        }
+        Some(symbol::SymbolKind::Module) => {
+            Some(format!("`{}` er en modul og kan ikke få en verdi", name))
+        }
+        Some(symbol::SymbolKind::Variable) if constant => Some(format!(
+            "`{}` er allerede en variabel og kan ikke bli en konstant",
+            name
+        )),
+        _ => None,
+    };
+    if let Some(message) = message {
+        diagnostics.push(Diagnostic::at_token(message, &token, name.chars().count()));
+        return;
+    }
+    let symbol = match existing {
+        Some(symbol) => symbol,
+        None if constant => symbol_table.declare(scope, name, symbol::SymbolKind::Constant),
+        None => symbol_table.declare(scope, name, symbol::SymbolKind::Variable),
+    };
+    identifier.token = Some(lexer::Token::new(
+        lexer::TokenType::Symbol(symbol),
+        token.line(),
+        token.column(),
+    ));
+}
+
+// The variables `ast` reads from outside the scope of an anonymous mechanism,
+// which it copies when it is made, globals included. The mechanism it is made
+// in may have returned by the time it is used, and copying the globals as well
+// keeps the rule the same wherever it is made.
+fn find_captures(
+    ast: &Node,
+    symbol_table: &symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    captures: &mut Vec<symbol::SymbolRef>,
+) {
+    if let Some(lexer::TokenType::Symbol(symbol_ref)) =
+        ast.token.as_ref().map(|token| token.token_type())
+    {
+        let symbol = symbol_table.get(*symbol_ref);
+        if symbol.kind.satisfies(&symbol::SymbolKind::Variable)
+            && !symbol_table.is_within(symbol.scope, scope)
+            && !captures.contains(symbol_ref)
+        {
+            captures.push(*symbol_ref);
+        }
+    }
+    for child in &ast.children {
+        find_captures(child, symbol_table, scope, captures);
+    }
+}
+
+fn resolve_reference(
+    node: &mut Box<Node>,
+    kind: symbol::SymbolKind,
+    symbol_table: &symbol::SymbolTable,
+    scope: symbol::ScopeRef,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(token) = node.token.clone() else {
+        return;
+    };
+    let lexer::TokenType::Identifier(name) = token.token_type() else {
+        return;
+    };
+    // A mechanism can be used as a value, and a variable holding one can be
+    // called, each when nothing of the kind asked for has the name.
+    let other = match kind {
+        symbol::SymbolKind::Variable => Some(symbol::SymbolKind::Function),
+        symbol::SymbolKind::Function => Some(symbol::SymbolKind::Variable),
+        _ => None,
+    };
+    let found = symbol_table
+        .lookup(scope, name, &kind)
+        .or_else(|| symbol_table.lookup(scope, name, other.as_ref()?));
+    match found {
+        Some(symbol) => {
+            node.token = Some(lexer::Token::new(
+                lexer::TokenType::Symbol(symbol),
+                token.line(),
+                token.column(),
+            ));
+            if kind == symbol::SymbolKind::Variable
+                && symbol_table.get(symbol).kind == symbol::SymbolKind::Function
+            {
+                node.node_type = NodeType::FunctionReference;
+            }
+        }
+        None => {
+            let message = match kind {
+                symbol::SymbolKind::Function => {
+                    format!("mekanismen `{}` er ikke definert", name)
+                }
+                symbol::SymbolKind::Struct => {
+                    format!("strukturen `{}` er ikke definert", name)
+                }
+                _ => format!("`{}` er ikke definert", name),
+            };
+            let mut diagnostic = Diagnostic::at_token(message, &token, name.chars().count());
+            if let Some(suggestion) = symbol_table.closest_name(scope, name, &kind) {
+                diagnostic = diagnostic.with_help(format!("mente du `{}`?", suggestion));
+            }
+            diagnostics.push(diagnostic);
+        }
     }
 }
 
@@ -225,11 +1099,14 @@ fn ast_to_graphwiz_aux(state: &mut AstWriterState, ast: &Node) -> Result<(), Box
     let id = state.id;
     state.id += 1;
     let label = format!("{:?}", ast.node_type);
-    let label = label.split("(").collect::<Vec<&str>>()[0];
+    let mut label = label.split("(").collect::<Vec<&str>>()[0].to_string();
+    if ast.type_ != symbol::Type::Unknown {
+        label = format!("{}: {:?}", label, ast.type_);
+    }
     state
         .file
         .write_all(format!("    {} [label=\"{}\"];\n", id, label).as_bytes())?;
-    if ast.data.len() > 0 {
+    if !ast.data.is_empty() {
         let data_id = state.id;
         state.id += 1;
         let mut data_label = ast.data.clone();
@@ -0,0 +1,285 @@
+use std::{cmp::Ordering, fmt};
+
+// Each limb holds nine decimal digits, which keeps printing simple and lets
+// the product of two limbs fit in a u64.
+const BASE: u64 = 1_000_000_000;
+
+// A whole number of any size, used when a result no longer fits in an i64.
+// The limbs are stored least significant first without leading zeros, and
+// zero is never negative, so equal numbers always look the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(value: i64) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        Self::new(value < 0, limbs)
+    }
+
+    // Reads a non-empty string of decimal digits.
+    pub fn from_digits(digits: &str) -> Self {
+        let digits = digits.as_bytes();
+        let limbs = digits
+            .rchunks(9)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |limb, digit| limb * 10 + (digit - b'0') as u32)
+            })
+            .collect();
+        Self::new(false, limbs)
+    }
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Self {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 3 {
+            return None;
+        }
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0i128, |total, limb| total * BASE as i128 + *limb as i128);
+        let value = if self.negative { -magnitude } else { magnitude };
+        i64::try_from(value).ok()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0.0, |total, limb| total * BASE as f64 + *limb as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return Self::new(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+        match compare_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => Self::new(other.negative, sub_magnitudes(&other.limbs, &self.limbs)),
+            _ => Self::new(self.negative, sub_magnitudes(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.negate())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        Self::new(
+            self.negative != other.negative,
+            mul_magnitudes(&self.limbs, &other.limbs),
+        )
+    }
+
+    // Division rounding towards zero, as `/` does for i64. The divisor is not
+    // zero.
+    pub fn div(&self, other: &BigInt) -> BigInt {
+        let (quotient, _) = div_mod_magnitudes(&self.limbs, &other.limbs);
+        Self::new(self.negative != other.negative, quotient)
+    }
+
+    // Division rounding down with the remainder taking the sign of the
+    // divisor, the same as for i64 in the simulator. The divisor is not zero.
+    pub fn div_mod_floor(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = div_mod_magnitudes(&self.limbs, &other.limbs);
+        let quotient = Self::new(self.negative != other.negative, quotient);
+        let remainder = Self::new(self.negative, remainder);
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            (quotient.sub(&BigInt::from_i64(1)), remainder.add(other))
+        } else {
+            (quotient, remainder)
+        }
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn negate(&self) -> BigInt {
+        Self::new(!self.negative, self.limbs.clone())
+    }
+
+    pub fn abs(&self) -> BigInt {
+        Self::new(false, self.limbs.clone())
+    }
+
+    // The greatest common divisor, which is never negative.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let remainder = a.div_mod_floor(&b).1;
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => compare_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", last)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+fn compare_magnitudes(left: &[u32], right: &[u32]) -> Ordering {
+    left.len()
+        .cmp(&right.len())
+        .then_with(|| left.iter().rev().cmp(right.iter().rev()))
+}
+
+fn add_magnitudes(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len().max(right.len()) + 1);
+    let mut carry = 0;
+    for i in 0..left.len().max(right.len()) {
+        let sum = *left.get(i).unwrap_or(&0) as u64 + *right.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// `left` must be at least as large as `right`.
+fn sub_magnitudes(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len());
+    let mut borrow = 0;
+    for (i, limb) in left.iter().enumerate() {
+        let mut difference = *limb as i64 - *right.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    result
+}
+
+fn mul_magnitudes(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u64; left.len() + right.len()];
+    for (i, a) in left.iter().enumerate() {
+        let mut carry = 0;
+        for (j, b) in right.iter().enumerate() {
+            let product = result[i + j] + *a as u64 * *b as u64 + carry;
+            result[i + j] = product % BASE;
+            carry = product / BASE;
+        }
+        let mut k = i + right.len();
+        while carry > 0 {
+            let sum = result[k] + carry;
+            result[k] = sum % BASE;
+            carry = sum / BASE;
+            k += 1;
+        }
+    }
+    result.into_iter().map(|limb| limb as u32).collect()
+}
+
+fn mul_small(left: &[u32], right: u32) -> Vec<u32> {
+    mul_magnitudes(left, &[right])
+}
+
+fn trim(mut limbs: Vec<u32>) -> Vec<u32> {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+    limbs
+}
+
+// Schoolbook long division, one limb of the quotient at a time. Each limb is
+// found by a binary search for the largest multiple of the divisor that fits.
+fn div_mod_magnitudes(left: &[u32], right: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0u32; left.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for i in (0..left.len()).rev() {
+        remainder.insert(0, left[i]);
+        remainder = trim(remainder);
+        let (mut low, mut high) = (0u32, (BASE - 1) as u32);
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            let product = trim(mul_small(right, middle));
+            if compare_magnitudes(&product, &remainder) == Ordering::Greater {
+                high = middle - 1;
+            } else {
+                low = middle;
+            }
+        }
+        if low > 0 {
+            remainder = trim(sub_magnitudes(&remainder, &trim(mul_small(right, low))));
+        }
+        quotient[i] = low;
+    }
+    (trim(quotient), remainder)
+}
@@ -0,0 +1,204 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    bigint::BigInt,
+    simulate::RuntimeError,
+    symbol::{ScopeKind, SymbolKind, SymbolTable, Type},
+    value::Value,
+};
+
+// A mechanism that comes with the language and runs natively in the
+// simulator, such as `kvadratrot`. Its name may be several words, as in
+// `utfør store bokstaver med navn!`.
+pub struct Builtin {
+    pub name: &'static str,
+    // The name and type of each parameter. A `desimaltall` parameter takes
+    // any number and an unknown one takes any value.
+    pub parameters: Vec<(&'static str, Type)>,
+    // The type of the result given the types of the arguments.
+    pub result: fn(&[Type]) -> Type,
+    pub run: fn(Vec<Value>) -> Result<Value, RuntimeError>,
+}
+
+impl Builtin {
+    pub fn accepts(&self, parameter: usize, type_: &Type) -> bool {
+        let expected = &self.parameters[parameter].1;
+        expected.merge(type_).is_some() || (*expected == Type::Float && type_.is_numeric())
+    }
+}
+
+pub fn builtins() -> Vec<Builtin> {
+    vec![
+        Builtin {
+            name: "absoluttverdi",
+            parameters: vec![("tall", Type::Float)],
+            result: same_number,
+            run: absolute_value,
+        },
+        Builtin {
+            name: "kvadratrot",
+            parameters: vec![("tall", Type::Float)],
+            result: |_| Type::Float,
+            run: square_root,
+        },
+        Builtin {
+            name: "største",
+            parameters: vec![("a", Type::Float), ("b", Type::Float)],
+            result: same_number,
+            run: |arguments| pick(arguments, std::cmp::Ordering::Greater),
+        },
+        Builtin {
+            name: "minste",
+            parameters: vec![("a", Type::Float), ("b", Type::Float)],
+            result: same_number,
+            run: |arguments| pick(arguments, std::cmp::Ordering::Less),
+        },
+        Builtin {
+            name: "store bokstaver",
+            parameters: vec![("tekst", Type::String)],
+            result: |_| Type::String,
+            run: |arguments| Ok(Value::String(string(&arguments[0]).to_uppercase())),
+        },
+        Builtin {
+            name: "del opp",
+            parameters: vec![("tekst", Type::String), ("skille", Type::String)],
+            result: |_| Type::List(Box::new(Type::String)),
+            run: split,
+        },
+        Builtin {
+            name: "som tall",
+            parameters: vec![("verdi", Type::Unknown)],
+            result: |_| Type::Int,
+            run: to_int,
+        },
+        Builtin {
+            name: "som tekst",
+            parameters: vec![("verdi", Type::Unknown)],
+            result: |_| Type::String,
+            run: |arguments| Ok(Value::String(arguments[0].to_string())),
+        },
+    ]
+}
+
+pub fn find(name: &str) -> Option<Builtin> {
+    builtins().into_iter().find(|builtin| builtin.name == name)
+}
+
+// Declares every built-in mechanism in the scope around the global one, with
+// its parameters in a scope of their own so they show up in the symbol table.
+pub fn declare(symbol_table: &mut SymbolTable) {
+    let outer = symbol_table.builtin_scope();
+    for builtin in builtins() {
+        let symbol = symbol_table.declare(outer, builtin.name, SymbolKind::Function);
+        let scope = symbol_table.add_scope(ScopeKind::Mechanism, outer);
+        let mut parameters = Vec::new();
+        for (name, type_) in &builtin.parameters {
+            let parameter = symbol_table.declare(scope, name, SymbolKind::Variable);
+            symbol_table.set_declared_type(parameter, type_.clone());
+            symbol_table.set_type(parameter, type_.clone());
+            parameters.push(parameter);
+        }
+        let types: Vec<Type> = builtin
+            .parameters
+            .iter()
+            .map(|(_, type_)| type_.clone())
+            .collect();
+        symbol_table.set_parameters(symbol, parameters);
+        symbol_table.set_type(symbol, (builtin.result)(&types));
+        symbol_table.set_builtin(symbol);
+    }
+}
+
+// Whole numbers give whole numbers, anything else a decimal number.
+fn same_number(arguments: &[Type]) -> Type {
+    if arguments.contains(&Type::Unknown) {
+        Type::Unknown
+    } else if arguments.iter().all(|type_| *type_ == Type::Int) {
+        Type::Int
+    } else {
+        Type::Float
+    }
+}
+
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(value) => value,
+        _ => panic!("Expected a string, got {}", value),
+    }
+}
+
+fn absolute_value(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let value = match &arguments[0] {
+        Value::Int(value) => match value.checked_abs() {
+            Some(value) => Value::Int(value),
+            None => Value::from_big_int(BigInt::from_i64(*value).abs()),
+        },
+        Value::BigInt(value) => Value::BigInt(value.abs()),
+        Value::Fraction(value) => Value::Fraction(value.abs()),
+        Value::Float(value) => Value::Float(value.abs()),
+        value => panic!("Expected a number, got {}", value),
+    };
+    Ok(value)
+}
+
+fn square_root(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let value = arguments[0].as_float().expect("Expected a number");
+    if value < 0.0 {
+        return Err(RuntimeError::new(format!(
+            "kan ikke ta kvadratroten av {}",
+            arguments[0]
+        )));
+    }
+    Ok(Value::Float(value.sqrt()))
+}
+
+fn pick(mut arguments: Vec<Value>, wanted: std::cmp::Ordering) -> Result<Value, RuntimeError> {
+    let right = arguments.pop().unwrap();
+    let left = arguments.pop().unwrap();
+    match right.compare(&left) {
+        Some(ordering) if ordering == wanted => Ok(right),
+        _ => Ok(left),
+    }
+}
+
+fn split(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (text, separator) = (string(&arguments[0]), string(&arguments[1]));
+    let parts: Vec<Value> = if separator.is_empty() {
+        text.split_whitespace()
+            .map(|part| Value::String(part.to_string()))
+            .collect()
+    } else {
+        text.split(separator)
+            .map(|part| Value::String(part.to_string()))
+            .collect()
+    };
+    Ok(Value::List(Rc::new(RefCell::new(parts))))
+}
+
+// Decimal numbers are cut to their whole part, as in `som tall` of 2.7 being 2.
+fn to_int(arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let value = match &arguments[0] {
+        Value::Int(_) | Value::BigInt(_) => arguments[0].clone(),
+        Value::Fraction(value) => Value::from_big_int(value.truncate()),
+        Value::Float(value) if value.is_finite() && value.abs() < i64::MAX as f64 => {
+            Value::Int(*value as i64)
+        }
+        Value::Bool(value) => Value::Int(*value as i64),
+        Value::String(text) => match Value::parse_int(text.trim()) {
+            Some(value) => value,
+            None => {
+                return Err(RuntimeError::new(format!(
+                    "kan ikke gjøre \"{}\" om til et tall",
+                    text
+                )))
+            }
+        },
+        value => {
+            return Err(RuntimeError::new(format!(
+                "kan ikke gjøre {} om til et tall",
+                value
+            )))
+        }
+    };
+    Ok(value)
+}
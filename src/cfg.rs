@@ -1,8 +1,6 @@
-use std::{error::Error, fs::File, io::Write};
+use std::{collections::HashMap, error::Error, fs::File, io::Write};
 
-use tracing::info;
-
-use crate::{ast, lexer, symbol, utils};
+use crate::{ast, bigint::BigInt, diagnostic::Span, lexer, symbol, types, utils};
 
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub enum Opcode {
@@ -10,22 +8,59 @@ pub enum Opcode {
     Sub,
     Mul,
     Div,
+    IntDiv,
+    Mod,
+    Pow,
     CmpEq,
+    CmpLt,
+    CmpLe,
+    CmpGt,
+    CmpGe,
     Call,
     Ret,
     Push,
+    Pop,
     Print,
     Set,
     If,
+    Switch,
+    ListNew,
+    ListPush,
+    ListGet,
+    ListLen,
+    MapNew,
+    MapInsert,
+    MapGet,
+    MapKeys,
+    Contains,
+    StructNew,
+    FieldLoad,
+    FieldStore,
+    Read,
+    Parse,
+    ReadFile,
+    WriteFile,
+    Try,
+    EndTry,
+    Catch,
+    Raise,
+    Assertions,
+    Trap,
+    Closure,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Operand {
     Immediate(i64),
+    BigInt(BigInt),
+    Float(f64),
+    Bool(bool),
     Label(usize),
     String(usize),
     Variable(symbol::SymbolRef),
     Temporary(usize),
+    Struct(symbol::SymbolRef),
+    Field(symbol::SymbolRef),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -33,6 +68,9 @@ pub struct Instruction {
     pub id: usize,
     pub opcode: Opcode,
     pub operands: Vec<Operand>,
+    // Where in the source the instruction comes from, for instructions that
+    // can fail while the program runs.
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,6 +87,17 @@ impl BasicBlock {
     }
 }
 
+// A loop being built, for `avbryt` and `hopp videre` to jump out of.
+#[derive(Debug, Clone, PartialEq)]
+struct Loop {
+    header: usize,
+    // The blocks that leave the loop, which only get their edge to the block
+    // after it once that is made.
+    breaks: Vec<usize>,
+    // How many `prøv` were open when the loop started.
+    tries: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CFG {
     blocks: Vec<BasicBlock>,
@@ -56,53 +105,131 @@ pub struct CFG {
     exit: usize,
     next_id: usize,
     next_temporary_variable_id: usize,
+    functions: HashMap<symbol::SymbolRef, usize>,
+    // The span given to the next instruction added.
+    span: Option<Span>,
+    loops: Vec<Loop>,
+    // How many `prøv` bodies the code being built is inside of.
+    tries: usize,
+}
+
+fn comparison(operator: &str) -> Opcode {
+    match operator {
+        "==" => Opcode::CmpEq,
+        "<" => Opcode::CmpLt,
+        "<=" => Opcode::CmpLe,
+        ">" => Opcode::CmpGt,
+        ">=" => Opcode::CmpGe,
+        _ => panic!("Unknown operator"),
+    }
+}
+
+// The lowest value and, for every whole number from it up to the highest, the
+// first case with that number, when every value is a whole number and at
+// least half of the numbers in between are values. A table for fewer than
+// three values saves nothing over comparing.
+fn jump_table(subject: &ast::Node, cases: &[Box<ast::Node>]) -> Option<(i64, Vec<Option<usize>>)> {
+    if subject.type_ != symbol::Type::Int {
+        return None;
+    }
+    let mut values = Vec::new();
+    for (case, node) in cases.iter().enumerate() {
+        for value in &node.children[..node.children.len() - 1] {
+            match ast::literal(value) {
+                Some(ast::Literal::Int(value)) => values.push((value, case)),
+                _ => return None,
+            }
+        }
+    }
+    if values.len() < 3 {
+        return None;
+    }
+    let low = values.iter().map(|(value, _)| *value).min()?;
+    let high = values.iter().map(|(value, _)| *value).max()?;
+    let slots = usize::try_from(high.checked_sub(low)?)
+        .ok()?
+        .checked_add(1)?;
+    if slots > 2 * values.len() {
+        return None;
+    }
+    let mut table = vec![None; slots];
+    for (value, case) in values {
+        let slot = &mut table[(value - low) as usize];
+        if slot.is_none() {
+            *slot = Some(case);
+        }
+    }
+    Some((low, table))
 }
 
 impl CFG {
     pub fn new() -> Self {
-        let cfg = Self {
+        Self {
             blocks: Vec::new(),
             entry: 0,
             exit: 0,
             next_id: 0,
             next_temporary_variable_id: 0,
-        };
-        cfg
+            functions: HashMap::new(),
+            span: None,
+            loops: Vec::new(),
+            tries: 0,
+        }
     }
 
-    pub fn build(&mut self, ast: &Box<ast::Node>) {
+    pub fn build(&mut self, ast: &ast::Node) {
         self.add_empty_entry_block();
-        self.create_basic_blocks(ast, self.entry);
-        self.add_empty_exit_block();
+        let last_id = self.create_basic_blocks(ast, self.entry);
+        self.add_empty_exit_block(last_id);
+        self.resolve_calls();
     }
 
-    fn create_basic_blocks(&mut self, ast: &Box<ast::Node>, parent_id: usize) -> usize {
+    fn create_basic_blocks(&mut self, ast: &ast::Node, parent_id: usize) -> usize {
         let mut seq_id = parent_id;
         match ast.node_type {
-            ast::NodeType::PrintStatement => match &ast.children[0].token {
-                Some(token) => match token.token_type() {
-                    lexer::TokenType::StringListIndex(string_id) => {
-                        let id = self.next_id();
-                        let instruction = Instruction {
-                            id,
-                            opcode: Opcode::Print,
-                            operands: vec![Operand::String(*string_id)],
-                        };
-                        let block = BasicBlock {
-                            id,
-                            instructions: vec![instruction],
-                            predecessors: vec![seq_id],
-                            successors: vec![],
-                        };
-                        self.blocks.push(block);
-                        seq_id = id;
+            ast::NodeType::PrintStatement => {
+                let expression = &ast.children[0];
+                let leaf_token = match expression.children.is_empty()
+                    && expression.node_type != ast::NodeType::FunctionReference
+                {
+                    true => expression.token.as_ref().map(|token| token.token_type()),
+                    false => None,
+                };
+                let operand = match leaf_token {
+                    Some(lexer::TokenType::StringListIndex(string_id)) => {
+                        Operand::String(*string_id)
+                    }
+                    // Reading a variable fails when it has no value yet. The
+                    // name is not known here, so the simulator gives the span
+                    // its length.
+                    Some(lexer::TokenType::Symbol(symbol)) => {
+                        self.span = expression
+                            .token
+                            .as_ref()
+                            .map(|token| Span::at_token(token, 1));
+                        Operand::Variable(*symbol)
                     }
-                    lexer::TokenType::Symbol(symbol) => {
+                    _ => {
+                        seq_id = self.create_basic_blocks(expression, seq_id);
+                        Operand::Temporary(self.get_last_temp_id())
+                    }
+                };
+                seq_id = self.add_instruction_block(Opcode::Print, vec![operand], seq_id);
+            }
+            ast::NodeType::AssignmentStatement => {
+                let expression = &ast.children[1];
+                seq_id = self.create_basic_blocks(expression, seq_id);
+
+                let temp_id = self.get_last_temp_id();
+                let identifier = &ast.children[0];
+                if let Some(token) = &identifier.token {
+                    if let lexer::TokenType::Symbol(symbol) = token.token_type() {
                         let id = self.next_id();
                         let instruction = Instruction {
                             id,
-                            opcode: Opcode::Print,
-                            operands: vec![Operand::Variable(symbol.clone())],
+                            opcode: Opcode::Set,
+                            operands: vec![Operand::Variable(*symbol), Operand::Temporary(temp_id)],
+                            span: None,
                         };
                         let block = BasicBlock {
                             id,
@@ -113,59 +240,6 @@ impl CFG {
                         self.blocks.push(block);
                         seq_id = id;
                     }
-                    _ => {
-                        panic!("Invalid token type for print statement");
-                    }
-                },
-                None => {
-                    let expression = &ast.children[0];
-                    seq_id = self.create_basic_blocks(expression, seq_id);
-                    let temp_id = self.get_last_temp_id();
-                    let id = self.next_id();
-                    let instruction = Instruction {
-                        id,
-                        opcode: Opcode::Print,
-                        operands: vec![Operand::Temporary(temp_id)],
-                    };
-                    let block = BasicBlock {
-                        id,
-                        instructions: vec![instruction],
-                        predecessors: vec![seq_id],
-                        successors: vec![],
-                    };
-                    self.blocks.push(block);
-                    seq_id = id;
-                }
-            },
-            ast::NodeType::AssignmentStatement => {
-                let expression = &ast.children[1];
-                seq_id = self.create_basic_blocks(expression, seq_id);
-
-                let temp_id = self.get_last_temp_id();
-                let identifier = &ast.children[0];
-                if let Some(token) = &identifier.token {
-                    match token.token_type() {
-                        lexer::TokenType::Symbol(symbol) => {
-                            let id = self.next_id();
-                            let instruction = Instruction {
-                                id,
-                                opcode: Opcode::Set,
-                                operands: vec![
-                                    Operand::Variable(symbol.clone()),
-                                    Operand::Temporary(temp_id),
-                                ],
-                            };
-                            let block = BasicBlock {
-                                id,
-                                instructions: vec![instruction],
-                                predecessors: vec![seq_id],
-                                successors: vec![],
-                            };
-                            self.blocks.push(block);
-                            seq_id = id;
-                        }
-                        _ => {}
-                    }
                 }
             }
             ast::NodeType::Expression => {
@@ -179,96 +253,30 @@ impl CFG {
                     let right_temp = self.get_last_temp_id();
 
                     // generate instruction
-                    match ast.data.as_str() {
-                        "+" => {
-                            let id = self.next_id();
-                            let temp_id = self.next_temp_id();
-                            let instruction = Instruction {
-                                id,
-                                opcode: Opcode::Add,
-                                operands: vec![
-                                    Operand::Temporary(temp_id),
-                                    Operand::Temporary(left_temp),
-                                    Operand::Temporary(right_temp),
-                                ],
-                            };
-                            let block = BasicBlock {
-                                id,
-                                instructions: vec![instruction],
-                                predecessors: vec![seq_id],
-                                successors: vec![],
-                            };
-                            self.blocks.push(block);
-                            seq_id = id;
-                        }
-                        "-" => {
-                            let id = self.next_id();
-                            let temp_id = self.next_temp_id();
-                            let instruction = Instruction {
-                                id,
-                                opcode: Opcode::Sub,
-                                operands: vec![
-                                    Operand::Temporary(temp_id),
-                                    Operand::Temporary(left_temp),
-                                    Operand::Temporary(right_temp),
-                                ],
-                            };
-                            let block = BasicBlock {
-                                id,
-                                instructions: vec![instruction],
-                                predecessors: vec![seq_id],
-                                successors: vec![],
-                            };
-                            self.blocks.push(block);
-                            seq_id = id;
-                        }
-                        "*" => {
-                            let id = self.next_id();
-                            let temp_id = self.next_temp_id();
-                            let instruction = Instruction {
-                                id,
-                                opcode: Opcode::Mul,
-                                operands: vec![
-                                    Operand::Temporary(temp_id),
-                                    Operand::Temporary(left_temp),
-                                    Operand::Temporary(right_temp),
-                                ],
-                            };
-                            let block = BasicBlock {
-                                id,
-                                instructions: vec![instruction],
-                                predecessors: vec![seq_id],
-                                successors: vec![],
-                            };
-                            self.blocks.push(block);
-                            seq_id = id;
-                        }
-                        "/" => {
-                            let id = self.next_id();
-                            let temp_id = self.next_temp_id();
-                            let instruction = Instruction {
-                                id,
-                                opcode: Opcode::Div,
-                                operands: vec![
-                                    Operand::Temporary(temp_id),
-                                    Operand::Temporary(left_temp),
-                                    Operand::Temporary(right_temp),
-                                ],
-                            };
-                            let block = BasicBlock {
-                                id,
-                                instructions: vec![instruction],
-                                predecessors: vec![seq_id],
-                                successors: vec![],
-                            };
-                            self.blocks.push(block);
-                            seq_id = id;
-                        }
-                        _ => {
-                            panic!("Unknown operator");
-                        }
-                    }
-                } else if ast.children.len() == 0 {
+                    let opcode = match ast.data.as_str() {
+                        "+" => Opcode::Add,
+                        "-" => Opcode::Sub,
+                        "*" => Opcode::Mul,
+                        "/" => Opcode::Div,
+                        "//" => Opcode::IntDiv,
+                        "%" => Opcode::Mod,
+                        "^" => Opcode::Pow,
+                        _ => panic!("Unknown operator"),
+                    };
+                    self.span = ast.token.as_ref().map(|token| {
+                        Span::at_token(token, types::operator_name(&ast.data).chars().count())
+                    });
+                    let temp_id = self.next_temp_id();
+                    seq_id = self.add_instruction_block(
+                        opcode,
+                        vec![
+                            Operand::Temporary(temp_id),
+                            Operand::Temporary(left_temp),
+                            Operand::Temporary(right_temp),
+                        ],
+                        seq_id,
+                    );
+                } else if ast.children.is_empty() {
                     if let Some(token) = &ast.token {
                         match token.token_type() {
                             lexer::TokenType::IntLiteral(value) => {
@@ -281,6 +289,7 @@ impl CFG {
                                         Operand::Temporary(temp_id),
                                         Operand::Immediate(*value),
                                     ],
+                                    span: None,
                                 };
                                 let block = BasicBlock {
                                     id,
@@ -291,6 +300,41 @@ impl CFG {
                                 self.blocks.push(block);
                                 seq_id = id;
                             }
+                            lexer::TokenType::BigIntLiteral(value) => {
+                                let temp_id = self.next_temp_id();
+                                seq_id = self.add_instruction_block(
+                                    Opcode::Set,
+                                    vec![
+                                        Operand::Temporary(temp_id),
+                                        Operand::BigInt(value.clone()),
+                                    ],
+                                    seq_id,
+                                );
+                            }
+                            lexer::TokenType::FloatLiteral(value) => {
+                                let temp_id = self.next_temp_id();
+                                seq_id = self.add_instruction_block(
+                                    Opcode::Set,
+                                    vec![Operand::Temporary(temp_id), Operand::Float(*value)],
+                                    seq_id,
+                                );
+                            }
+                            lexer::TokenType::BoolLiteral(value) => {
+                                let temp_id = self.next_temp_id();
+                                seq_id = self.add_instruction_block(
+                                    Opcode::Set,
+                                    vec![Operand::Temporary(temp_id), Operand::Bool(*value)],
+                                    seq_id,
+                                );
+                            }
+                            lexer::TokenType::StringListIndex(string_id) => {
+                                let temp_id = self.next_temp_id();
+                                seq_id = self.add_instruction_block(
+                                    Opcode::Set,
+                                    vec![Operand::Temporary(temp_id), Operand::String(*string_id)],
+                                    seq_id,
+                                );
+                            }
                             lexer::TokenType::Symbol(symbol) => {
                                 let id = self.next_id();
                                 let temp_id = self.next_temp_id();
@@ -299,8 +343,9 @@ impl CFG {
                                     opcode: Opcode::Set,
                                     operands: vec![
                                         Operand::Temporary(temp_id),
-                                        Operand::Variable(symbol.clone()),
+                                        Operand::Variable(*symbol),
                                     ],
+                                    span: Some(Span::at_token(token, 1)),
                                 };
                                 let block = BasicBlock {
                                     id,
@@ -320,6 +365,28 @@ impl CFG {
                     }
                 }
             }
+            // `minus x` is computed as `0 minus x`.
+            ast::NodeType::Negation => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let operand_temp = self.get_last_temp_id();
+                let zero_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Set,
+                    vec![Operand::Temporary(zero_temp), Operand::Immediate(0)],
+                    seq_id,
+                );
+                let temp_id = self.next_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 5));
+                seq_id = self.add_instruction_block(
+                    Opcode::Sub,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(zero_temp),
+                        Operand::Temporary(operand_temp),
+                    ],
+                    seq_id,
+                );
+            }
             ast::NodeType::IfStatement => {
                 // empty start block
                 let empty_start_id = self.next_id();
@@ -342,6 +409,7 @@ impl CFG {
                     id: self.next_id(),
                     opcode: Opcode::If,
                     operands: vec![Operand::Temporary(condition_temp)],
+                    span: None,
                 };
                 let if_id = self.next_id();
                 let if_block = BasicBlock {
@@ -375,6 +443,179 @@ impl CFG {
                 self.blocks.push(end_block);
                 seq_id = end_id;
             }
+            ast::NodeType::ForStatement => {
+                // The loop keeps a counter of how many elements it has taken
+                // and checks it against the length on every round, so a body
+                // that appends to the list also visits the new elements.
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let mut list_temp = self.get_last_temp_id();
+                // An `oppslag` is gone through by a list of its keys, taken
+                // before the first round.
+                if let symbol::Type::Map(..) = ast.children[1].type_ {
+                    let keys_temp = self.next_temp_id();
+                    seq_id = self.add_instruction_block(
+                        Opcode::MapKeys,
+                        vec![Operand::Temporary(keys_temp), Operand::Temporary(list_temp)],
+                        seq_id,
+                    );
+                    list_temp = keys_temp;
+                }
+                let index_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Set,
+                    vec![Operand::Temporary(index_temp), Operand::Immediate(0)],
+                    seq_id,
+                );
+
+                let header_id = self.next_id();
+                self.blocks.push(BasicBlock {
+                    id: header_id,
+                    instructions: Vec::new(),
+                    predecessors: vec![seq_id],
+                    successors: vec![],
+                });
+                self.enter_loop(header_id);
+                let length_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::ListLen,
+                    vec![
+                        Operand::Temporary(length_temp),
+                        Operand::Temporary(list_temp),
+                    ],
+                    header_id,
+                );
+                let condition_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::CmpLt,
+                    vec![
+                        Operand::Temporary(condition_temp),
+                        Operand::Temporary(index_temp),
+                        Operand::Temporary(length_temp),
+                    ],
+                    seq_id,
+                );
+                let if_id = self.add_instruction_block(
+                    Opcode::If,
+                    vec![Operand::Temporary(condition_temp)],
+                    seq_id,
+                );
+
+                // Elements are counted from 1, so step the counter before
+                // taking the element.
+                let one_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Set,
+                    vec![Operand::Temporary(one_temp), Operand::Immediate(1)],
+                    if_id,
+                );
+                seq_id = self.add_instruction_block(
+                    Opcode::Add,
+                    vec![
+                        Operand::Temporary(index_temp),
+                        Operand::Temporary(index_temp),
+                        Operand::Temporary(one_temp),
+                    ],
+                    seq_id,
+                );
+                let element_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::ListGet,
+                    vec![
+                        Operand::Temporary(element_temp),
+                        Operand::Temporary(list_temp),
+                        Operand::Temporary(index_temp),
+                    ],
+                    seq_id,
+                );
+                if let Some(lexer::TokenType::Symbol(symbol)) =
+                    ast.children[0].token.as_ref().map(|t| t.token_type())
+                {
+                    seq_id = self.add_instruction_block(
+                        Opcode::Set,
+                        vec![Operand::Variable(*symbol), Operand::Temporary(element_temp)],
+                        seq_id,
+                    );
+                }
+                let body_id = self.create_basic_blocks(&ast.children[2], seq_id);
+                self.add_predecessor(header_id, body_id);
+
+                seq_id = self.add_empty_block(if_id);
+                self.leave_loop(seq_id);
+            }
+            ast::NodeType::WhileStatement => {
+                let header_id = self.add_empty_block(seq_id);
+                self.enter_loop(header_id);
+                seq_id = self.create_basic_blocks(&ast.children[0], header_id);
+                let condition_temp = self.get_last_temp_id();
+                let if_id = self.add_instruction_block(
+                    Opcode::If,
+                    vec![Operand::Temporary(condition_temp)],
+                    seq_id,
+                );
+                let body_id = self.create_basic_blocks(&ast.children[1], if_id);
+                self.add_predecessor(header_id, body_id);
+
+                seq_id = self.add_empty_block(if_id);
+                self.leave_loop(seq_id);
+            }
+            // A case is chosen by a single `Switch` when the cases are whole
+            // numbers close enough together to fill a table, and otherwise by
+            // comparing with each case in turn.
+            ast::NodeType::SwitchStatement => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let subject_temp = self.get_last_temp_id();
+                let (default, cases) = match ast.children.last() {
+                    Some(last) if last.children.len() == 1 => (
+                        Some(&last.children[0]),
+                        &ast.children[1..ast.children.len() - 1],
+                    ),
+                    _ => (None, &ast.children[1..]),
+                };
+                let (default_id, mut ends) = match jump_table(&ast.children[0], cases) {
+                    Some((low, slots)) => {
+                        self.add_jump_table(subject_temp, low, &slots, cases, seq_id)
+                    }
+                    None => self.add_comparisons(subject_temp, cases, seq_id),
+                };
+                ends.push(match default {
+                    Some(body) => self.create_basic_blocks(body, default_id),
+                    None => default_id,
+                });
+
+                seq_id = self.next_id();
+                self.blocks.push(BasicBlock {
+                    id: seq_id,
+                    instructions: Vec::new(),
+                    predecessors: ends,
+                    successors: vec![],
+                });
+            }
+            // Both jump to a block of the innermost loop, first leaving any
+            // `prøv` started inside it. What follows them in the same body can
+            // never run, so it goes on from a block without predecessors.
+            ast::NodeType::Break | ast::NodeType::Continue => {
+                let target = self
+                    .loops
+                    .last()
+                    .cloned()
+                    .expect("Loop control outside of a loop");
+                for _ in target.tries..self.tries {
+                    seq_id = self.add_instruction_block(Opcode::EndTry, vec![], seq_id);
+                }
+                let jump_id = self.add_empty_block(seq_id);
+                if ast.node_type == ast::NodeType::Break {
+                    self.loops.last_mut().unwrap().breaks.push(jump_id);
+                } else {
+                    self.add_predecessor(target.header, jump_id);
+                }
+                seq_id = self.next_id();
+                self.blocks.push(BasicBlock {
+                    id: seq_id,
+                    instructions: Vec::new(),
+                    predecessors: Vec::new(),
+                    successors: vec![],
+                });
+            }
             ast::NodeType::Condition => {
                 let expr1 = &ast.children[0];
                 seq_id = self.create_basic_blocks(expr1, seq_id);
@@ -384,32 +625,452 @@ impl CFG {
                 seq_id = self.create_basic_blocks(expr2, seq_id);
                 let expr2_temp = self.get_last_temp_id();
 
-                match ast.data.as_str() {
-                    "==" => {
-                        let id = self.next_id();
-                        let temp_id = self.next_temp_id();
-                        let instruction = Instruction {
-                            id,
-                            opcode: Opcode::CmpEq,
-                            operands: vec![
-                                Operand::Temporary(temp_id),
-                                Operand::Temporary(expr1_temp),
-                                Operand::Temporary(expr2_temp),
-                            ],
-                        };
-                        let block = BasicBlock {
-                            id,
-                            instructions: vec![instruction],
-                            predecessors: vec![seq_id],
-                            successors: vec![],
-                        };
-                        self.blocks.push(block);
-                        seq_id = id;
-                    }
-                    _ => {
-                        panic!("Unknown operator");
-                    }
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    comparison(&ast.data),
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(expr1_temp),
+                        Operand::Temporary(expr2_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::FunctionDefinition => {
+                let symbol = match ast.children[0].token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Mechanism definition without a resolved name"),
+                };
+                self.add_function_body(symbol, &ast.children[1], &ast.children[2]);
+            }
+            // The body is placed like that of a named mechanism, and where the
+            // mechanism is written it becomes a value.
+            ast::NodeType::AnonymousFunction => {
+                let symbol = match ast.token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Anonymous mechanism without a symbol"),
+                };
+                self.add_function_body(symbol, &ast.children[0], &ast.children[1]);
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Closure,
+                    vec![Operand::Temporary(temp_id), Operand::Variable(symbol)],
+                    seq_id,
+                );
+            }
+            ast::NodeType::FunctionReference => {
+                let symbol = match ast.token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Mechanism reference without a resolved name"),
+                };
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Closure,
+                    vec![Operand::Temporary(temp_id), Operand::Variable(symbol)],
+                    seq_id,
+                );
+            }
+            ast::NodeType::ReturnStatement => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let temp_id = self.get_last_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Ret,
+                    vec![Operand::Temporary(temp_id)],
+                    seq_id,
+                );
+            }
+            ast::NodeType::FunctionCall => {
+                let symbol = match ast.children[0].token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Mechanism call without a resolved name"),
+                };
+                for argument in ast.children.iter().skip(1) {
+                    seq_id = self.create_basic_blocks(argument, seq_id);
+                    let argument_temp = self.get_last_temp_id();
+                    seq_id = self.add_instruction_block(
+                        Opcode::Push,
+                        vec![Operand::Temporary(argument_temp)],
+                        seq_id,
+                    );
+                }
+                let temp_id = self.next_temp_id();
+                // Built-in mechanisms can fail, which points at the `utfør`.
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 5));
+                seq_id = self.add_instruction_block(
+                    Opcode::Call,
+                    vec![Operand::Temporary(temp_id), Operand::Variable(symbol)],
+                    seq_id,
+                );
+            }
+            // The mechanism is found first and called through its temporary,
+            // with the number of arguments so that the simulator can check it
+            // against a mechanism whose type was not known.
+            ast::NodeType::IndirectCall => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let callee_temp = self.get_last_temp_id();
+                for argument in ast.children.iter().skip(1) {
+                    seq_id = self.create_basic_blocks(argument, seq_id);
+                    let argument_temp = self.get_last_temp_id();
+                    seq_id = self.add_instruction_block(
+                        Opcode::Push,
+                        vec![Operand::Temporary(argument_temp)],
+                        seq_id,
+                    );
+                }
+                let temp_id = self.next_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 5));
+                seq_id = self.add_instruction_block(
+                    Opcode::Call,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(callee_temp),
+                        Operand::Immediate(ast.children.len() as i64 - 1),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::ListLiteral => {
+                let list_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::ListNew,
+                    vec![Operand::Temporary(list_temp)],
+                    seq_id,
+                );
+                for element in &ast.children {
+                    seq_id = self.create_basic_blocks(element, seq_id);
+                    let element_temp = self.get_last_temp_id();
+                    seq_id = self.add_instruction_block(
+                        Opcode::ListPush,
+                        vec![
+                            Operand::Temporary(list_temp),
+                            Operand::Temporary(element_temp),
+                        ],
+                        seq_id,
+                    );
+                }
+                // The elements used temporaries of their own, so copy the list
+                // into the last one where the parent expects to find it.
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Set,
+                    vec![Operand::Temporary(temp_id), Operand::Temporary(list_temp)],
+                    seq_id,
+                );
+            }
+            ast::NodeType::Element => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let index_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let list_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                let opcode = match ast.children[1].type_ {
+                    symbol::Type::Map(..) => Opcode::MapGet,
+                    _ => Opcode::ListGet,
+                };
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 7));
+                seq_id = self.add_instruction_block(
+                    opcode,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(list_temp),
+                        Operand::Temporary(index_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::ListLength => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let list_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::ListLen,
+                    vec![Operand::Temporary(temp_id), Operand::Temporary(list_temp)],
+                    seq_id,
+                );
+            }
+            // The answer is read as a string, and then turned into a number
+            // if that is what it is used as.
+            ast::NodeType::Read => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let prompt_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 6));
+                seq_id = self.add_instruction_block(
+                    Opcode::Read,
+                    vec![Operand::Temporary(temp_id), Operand::Temporary(prompt_temp)],
+                    seq_id,
+                );
+                if ast.type_.is_numeric() {
+                    let number_temp = self.next_temp_id();
+                    self.span = ast.token.as_ref().map(|token| Span::at_token(token, 6));
+                    seq_id = self.add_instruction_block(
+                        Opcode::Parse,
+                        vec![
+                            Operand::Temporary(number_temp),
+                            Operand::Temporary(temp_id),
+                            Operand::Bool(ast.type_ == symbol::Type::Int),
+                        ],
+                        seq_id,
+                    );
+                }
+            }
+            ast::NodeType::FileRead => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let name_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 3));
+                seq_id = self.add_instruction_block(
+                    Opcode::ReadFile,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(name_temp),
+                        Operand::Bool(ast.data == "linjene"),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::FileWrite => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let value_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let name_temp = self.get_last_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 5));
+                seq_id = self.add_instruction_block(
+                    Opcode::WriteFile,
+                    vec![
+                        Operand::Temporary(name_temp),
+                        Operand::Temporary(value_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            // The block holding `Try` has two successors: the body, and the
+            // handler as an exceptional edge that is only taken when something
+            // in the body fails, however deep in mechanism calls that is.
+            ast::NodeType::TryStatement => {
+                let try_id = self.add_instruction_block(Opcode::Try, vec![], seq_id);
+                self.tries += 1;
+                let body_id = self.create_basic_blocks(&ast.children[0], try_id);
+                self.tries -= 1;
+                let end_try_id = self.add_instruction_block(Opcode::EndTry, vec![], body_id);
+
+                let operands = match ast.children.get(2).and_then(|name| name.token.as_ref()) {
+                    Some(token) => match token.token_type() {
+                        lexer::TokenType::Symbol(symbol) => vec![Operand::Variable(*symbol)],
+                        _ => vec![],
+                    },
+                    None => vec![],
+                };
+                let catch_id = self.add_instruction_block(Opcode::Catch, operands, try_id);
+                let handler_id = self.create_basic_blocks(&ast.children[1], catch_id);
+
+                let end_id = self.next_id();
+                self.blocks.push(BasicBlock {
+                    id: end_id,
+                    instructions: Vec::new(),
+                    predecessors: vec![end_try_id, handler_id],
+                    successors: vec![],
+                });
+                seq_id = end_id;
+            }
+            ast::NodeType::Raise => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let message_temp = self.get_last_temp_id();
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 4));
+                seq_id = self.add_instruction_block(
+                    Opcode::Raise,
+                    vec![Operand::Temporary(message_temp)],
+                    seq_id,
+                );
+            }
+            // `Assertions` skips the whole check when they are turned off.
+            // Otherwise the condition is computed, keeping both sides of a
+            // comparison so that `Trap` can show them if it does not hold.
+            ast::NodeType::Assert => {
+                let guard_id = self.add_instruction_block(Opcode::Assertions, vec![], seq_id);
+                let condition = &ast.children[0];
+                let mut sides = Vec::new();
+                if condition.node_type == ast::NodeType::Condition {
+                    seq_id = self.create_basic_blocks(&condition.children[0], guard_id);
+                    let left_temp = self.get_last_temp_id();
+                    seq_id = self.create_basic_blocks(&condition.children[1], seq_id);
+                    let right_temp = self.get_last_temp_id();
+                    let temp_id = self.next_temp_id();
+                    seq_id = self.add_instruction_block(
+                        comparison(&condition.data),
+                        vec![
+                            Operand::Temporary(temp_id),
+                            Operand::Temporary(left_temp),
+                            Operand::Temporary(right_temp),
+                        ],
+                        seq_id,
+                    );
+                    sides = vec![
+                        Operand::Temporary(left_temp),
+                        Operand::Temporary(right_temp),
+                    ];
+                } else {
+                    seq_id = self.create_basic_blocks(condition, guard_id);
                 }
+                let condition_temp = self.get_last_temp_id();
+                let if_id = self.add_instruction_block(
+                    Opcode::If,
+                    vec![Operand::Temporary(condition_temp)],
+                    seq_id,
+                );
+                let holds_id = self.add_empty_block(if_id);
+                self.span = ast.token.as_ref().map(|token| Span::at_token(token, 5));
+                self.add_instruction_block(Opcode::Trap, sides, if_id);
+
+                let end_id = self.next_id();
+                self.blocks.push(BasicBlock {
+                    id: end_id,
+                    instructions: Vec::new(),
+                    predecessors: vec![holds_id, guard_id],
+                    successors: vec![],
+                });
+                seq_id = end_id;
+            }
+            ast::NodeType::StructDefinition => {}
+            ast::NodeType::StructLiteral => {
+                // The fields are given in the order they are written, each
+                // as the field followed by the temporary holding its value.
+                let structure = match ast.children[0].token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Structure without a resolved name"),
+                };
+                let mut fields = Vec::new();
+                for field_value in ast.children.iter().skip(1) {
+                    let field = match field_value.children[0]
+                        .token
+                        .as_ref()
+                        .map(|t| t.token_type())
+                    {
+                        Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                        _ => panic!("Field without a resolved name"),
+                    };
+                    seq_id = self.create_basic_blocks(&field_value.children[1], seq_id);
+                    fields.push(Operand::Field(field));
+                    fields.push(Operand::Temporary(self.get_last_temp_id()));
+                }
+                let temp_id = self.next_temp_id();
+                let mut operands = vec![Operand::Temporary(temp_id), Operand::Struct(structure)];
+                operands.append(&mut fields);
+                seq_id = self.add_instruction_block(Opcode::StructNew, operands, seq_id);
+            }
+            ast::NodeType::FieldAccess => {
+                let field = match ast.children[0].token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Field without a resolved name"),
+                };
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let struct_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::FieldLoad,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(struct_temp),
+                        Operand::Field(field),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::FieldAssignment => {
+                let access = &ast.children[0];
+                let field = match access.children[0].token.as_ref().map(|t| t.token_type()) {
+                    Some(lexer::TokenType::Symbol(symbol)) => *symbol,
+                    _ => panic!("Field without a resolved name"),
+                };
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let value_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&access.children[1], seq_id);
+                let struct_temp = self.get_last_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::FieldStore,
+                    vec![
+                        Operand::Temporary(struct_temp),
+                        Operand::Field(field),
+                        Operand::Temporary(value_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::MapLiteral => {
+                let map_temp = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::MapNew,
+                    vec![Operand::Temporary(map_temp)],
+                    seq_id,
+                );
+                for entry in &ast.children {
+                    seq_id = self.create_basic_blocks(&entry.children[0], seq_id);
+                    let key_temp = self.get_last_temp_id();
+                    seq_id = self.create_basic_blocks(&entry.children[1], seq_id);
+                    let value_temp = self.get_last_temp_id();
+                    seq_id = self.add_instruction_block(
+                        Opcode::MapInsert,
+                        vec![
+                            Operand::Temporary(map_temp),
+                            Operand::Temporary(key_temp),
+                            Operand::Temporary(value_temp),
+                        ],
+                        seq_id,
+                    );
+                }
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Set,
+                    vec![Operand::Temporary(temp_id), Operand::Temporary(map_temp)],
+                    seq_id,
+                );
+            }
+            ast::NodeType::MapInsert => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let key_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let value_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[2], seq_id);
+                let map_temp = self.get_last_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::MapInsert,
+                    vec![
+                        Operand::Temporary(map_temp),
+                        Operand::Temporary(key_temp),
+                        Operand::Temporary(value_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::Contains => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let item_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let container_temp = self.get_last_temp_id();
+                let temp_id = self.next_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::Contains,
+                    vec![
+                        Operand::Temporary(temp_id),
+                        Operand::Temporary(container_temp),
+                        Operand::Temporary(item_temp),
+                    ],
+                    seq_id,
+                );
+            }
+            ast::NodeType::ListAppend => {
+                seq_id = self.create_basic_blocks(&ast.children[0], seq_id);
+                let value_temp = self.get_last_temp_id();
+                seq_id = self.create_basic_blocks(&ast.children[1], seq_id);
+                let list_temp = self.get_last_temp_id();
+                seq_id = self.add_instruction_block(
+                    Opcode::ListPush,
+                    vec![
+                        Operand::Temporary(list_temp),
+                        Operand::Temporary(value_temp),
+                    ],
+                    seq_id,
+                );
             }
             _ => {
                 for child in &ast.children {
@@ -417,7 +1078,214 @@ impl CFG {
                 }
             }
         }
-        return seq_id;
+        seq_id
+    }
+
+    // Appends a block holding a single instruction that follows `predecessor`
+    // and returns its id.
+    fn add_instruction_block(
+        &mut self,
+        opcode: Opcode,
+        operands: Vec<Operand>,
+        predecessor: usize,
+    ) -> usize {
+        let id = self.next_id();
+        let instruction = Instruction {
+            id,
+            opcode,
+            operands,
+            span: self.span.take(),
+        };
+        self.blocks.push(BasicBlock {
+            id,
+            instructions: vec![instruction],
+            predecessors: vec![predecessor],
+            successors: vec![],
+        });
+        id
+    }
+
+    // The body gets its own entry block without predecessors, so it is only
+    // reached through `Call` and leaves the current flow as is. Loops and
+    // `prøv` around the definition are not around the body.
+    fn add_function_body(
+        &mut self,
+        symbol: symbol::SymbolRef,
+        parameters: &ast::Node,
+        body: &ast::Node,
+    ) {
+        let loops = std::mem::take(&mut self.loops);
+        let tries = std::mem::take(&mut self.tries);
+        let entry_id = self.next_id();
+        self.blocks.push(BasicBlock {
+            id: entry_id,
+            instructions: Vec::new(),
+            predecessors: Vec::new(),
+            successors: vec![],
+        });
+        self.functions.insert(symbol, entry_id);
+
+        // Arguments are pushed in order by the caller, so they come off the
+        // stack last parameter first.
+        let mut body_id = entry_id;
+        for parameter in parameters.children.iter().rev() {
+            if let Some(lexer::TokenType::Symbol(parameter)) =
+                parameter.children[0].token.as_ref().map(|t| t.token_type())
+            {
+                body_id = self.add_instruction_block(
+                    Opcode::Pop,
+                    vec![Operand::Variable(*parameter)],
+                    body_id,
+                );
+            }
+        }
+        body_id = self.create_basic_blocks(body, body_id);
+        self.add_instruction_block(Opcode::Ret, vec![], body_id);
+        self.loops = loops;
+        self.tries = tries;
+    }
+
+    // Jumps straight to the case for the value of `subject_temp`, or to the
+    // returned block when no case has it. Each slot of the table gets a block
+    // of its own, in order and before the one for no case, as that is the
+    // order `Switch` counts its successors in. Returns that block and the
+    // last block of every case.
+    fn add_jump_table(
+        &mut self,
+        subject_temp: usize,
+        low: i64,
+        slots: &[Option<usize>],
+        cases: &[Box<ast::Node>],
+        predecessor: usize,
+    ) -> (usize, Vec<usize>) {
+        let switch_id = self.add_instruction_block(
+            Opcode::Switch,
+            vec![
+                Operand::Temporary(subject_temp),
+                Operand::Immediate(low),
+                Operand::Immediate(slots.len() as i64),
+            ],
+            predecessor,
+        );
+        let slot_ids: Vec<usize> = slots
+            .iter()
+            .map(|_| self.add_empty_block(switch_id))
+            .collect();
+        let default_id = self.add_empty_block(switch_id);
+        let mut entries: Vec<Vec<usize>> = vec![Vec::new(); cases.len()];
+        for (slot, slot_id) in slots.iter().zip(slot_ids) {
+            match slot {
+                Some(case) => entries[*case].push(slot_id),
+                None => self.add_predecessor(default_id, slot_id),
+            }
+        }
+        let mut ends = Vec::new();
+        for (case, slot_ids) in cases.iter().zip(entries) {
+            if let Some(entry_id) = self.join(&slot_ids) {
+                ends.push(self.create_basic_blocks(case.children.last().unwrap(), entry_id));
+            }
+        }
+        (default_id, ends)
+    }
+
+    // A block that all of `ids` go on to, or the only one of them.
+    fn join(&mut self, ids: &[usize]) -> Option<usize> {
+        match ids {
+            [] => None,
+            [id] => Some(*id),
+            [first, rest @ ..] => {
+                let id = self.add_empty_block(*first);
+                for predecessor in rest {
+                    self.add_predecessor(id, *predecessor);
+                }
+                Some(id)
+            }
+        }
+    }
+
+    // Compares `subject_temp` with each value of each case in turn and goes on
+    // to the returned block when none is equal. Returns that block and the last
+    // block of every case.
+    fn add_comparisons(
+        &mut self,
+        subject_temp: usize,
+        cases: &[Box<ast::Node>],
+        predecessor: usize,
+    ) -> (usize, Vec<usize>) {
+        let mut next_id = predecessor;
+        let mut ends = Vec::new();
+        for case in cases {
+            let (body, values) = case.children.split_last().unwrap();
+            let mut matches = Vec::new();
+            for value in values {
+                next_id = self.create_basic_blocks(value, next_id);
+                let value_temp = self.get_last_temp_id();
+                let condition_temp = self.next_temp_id();
+                next_id = self.add_instruction_block(
+                    Opcode::CmpEq,
+                    vec![
+                        Operand::Temporary(condition_temp),
+                        Operand::Temporary(subject_temp),
+                        Operand::Temporary(value_temp),
+                    ],
+                    next_id,
+                );
+                let if_id = self.add_instruction_block(
+                    Opcode::If,
+                    vec![Operand::Temporary(condition_temp)],
+                    next_id,
+                );
+                matches.push(self.add_empty_block(if_id));
+                next_id = self.add_empty_block(if_id);
+            }
+            let entry_id = self.join(&matches).unwrap();
+            ends.push(self.create_basic_blocks(body, entry_id));
+        }
+        (next_id, ends)
+    }
+
+    // Calls are emitted with the symbol of the mechanism, since it may be
+    // defined further down. Once every body is placed they are pointed at the
+    // entry block of the callee. Built-in mechanisms have no body and keep
+    // their symbol, the simulator runs them itself.
+    fn resolve_calls(&mut self) {
+        for block in &mut self.blocks {
+            for instruction in &mut block.instructions {
+                if instruction.opcode != Opcode::Call {
+                    continue;
+                }
+                if let Operand::Variable(symbol) = instruction.operands[1] {
+                    if let Some(entry) = self.functions.get(&symbol) {
+                        instruction.operands[1] = Operand::Label(*entry);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_predecessor(&mut self, id: usize, predecessor: usize) {
+        self.blocks
+            .iter_mut()
+            .find(|block| block.id == id)
+            .unwrap()
+            .predecessors
+            .push(predecessor);
+    }
+
+    fn enter_loop(&mut self, header: usize) {
+        self.loops.push(Loop {
+            header,
+            breaks: Vec::new(),
+            tries: self.tries,
+        });
+    }
+
+    // Points every `avbryt` of the innermost loop at `exit`.
+    fn leave_loop(&mut self, exit: usize) {
+        let breaks = self.loops.pop().unwrap().breaks;
+        for jump in breaks {
+            self.add_predecessor(exit, jump);
+        }
     }
 
     fn add_empty_entry_block(&mut self) {
@@ -432,10 +1300,20 @@ impl CFG {
         self.blocks.push(block);
     }
 
-    fn add_empty_exit_block(&mut self) {
+    fn add_empty_block(&mut self, predecessor: usize) -> usize {
+        let id = self.next_id();
+        self.blocks.push(BasicBlock {
+            id,
+            instructions: Vec::new(),
+            predecessors: vec![predecessor],
+            successors: Vec::new(),
+        });
+        id
+    }
+
+    fn add_empty_exit_block(&mut self, predecessor: usize) {
         let id = self.next_id();
         self.exit = id;
-        let predecessor = self.blocks[self.blocks.len() - 1].id;
         let block = BasicBlock {
             id,
             instructions: Vec::new(),
@@ -471,6 +1349,14 @@ impl CFG {
         successors
     }
 
+    // The entry block of the body of a mechanism that is not built in.
+    pub fn function_entry(&self, symbol: symbol::SymbolRef) -> usize {
+        *self
+            .functions
+            .get(&symbol)
+            .expect("Mechanism without a body")
+    }
+
     pub fn entry_block(&self) -> usize {
         self.entry
     }
@@ -479,8 +1365,23 @@ impl CFG {
         self.exit
     }
 
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+    }
+
+    pub fn instructions_mut(&mut self) -> impl Iterator<Item = &mut Instruction> {
+        self.blocks
+            .iter_mut()
+            .flat_map(|block| block.instructions.iter_mut())
+    }
+
     pub fn get_block(&self, id: usize) -> &BasicBlock {
-        &self.blocks[id]
+        self.blocks
+            .iter()
+            .find(|block| block.id == id)
+            .expect("No block with the given id")
     }
 
     pub fn write_to_graphwiz(&self, filename: &str) -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,102 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufRead, Write},
+};
+
+// Where a running program writes what it says with `si`, gets its answers
+// for `svaret på` from and keeps its files, so that it can run outside a
+// terminal.
+pub trait Console {
+    fn write(&mut self, text: &str);
+    // The next line of input without its line break, or None when there is
+    // nothing more to read.
+    fn read_line(&mut self) -> Option<String>;
+    fn read_file(&mut self, name: &str) -> io::Result<String>;
+    fn write_file(&mut self, name: &str, contents: &str) -> io::Result<()>;
+}
+
+// Standard output, standard input and the file system.
+pub struct Terminal;
+
+impl Console for Terminal {
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        // A question is written without a line break, so make sure it shows.
+        io::stdout().flush().expect("Failed to write to stdout");
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    }
+
+    fn read_file(&mut self, name: &str) -> io::Result<String> {
+        fs::read_to_string(name)
+    }
+
+    fn write_file(&mut self, name: &str, contents: &str) -> io::Result<()> {
+        fs::write(name, contents)
+    }
+}
+
+// Keeps everything the program writes and answers from lines given up front,
+// for tests and for running programs from inside other programs. Its files
+// only live in memory.
+#[derive(Debug, Default)]
+pub struct Capture {
+    input: VecDeque<String>,
+    output: String,
+    files: HashMap<String, String>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input(input: &str) -> Self {
+        Self {
+            input: input.lines().map(str::to_string).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_file(mut self, name: &str, contents: &str) -> Self {
+        self.files.insert(name.to_string(), contents.to_string());
+        self
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn file(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(String::as_str)
+    }
+}
+
+impl Console for Capture {
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+
+    fn read_file(&mut self, name: &str) -> io::Result<String> {
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write_file(&mut self, name: &str, contents: &str) -> io::Result<()> {
+        self.files.insert(name.to_string(), contents.to_string());
+        Ok(())
+    }
+}
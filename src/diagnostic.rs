@@ -0,0 +1,122 @@
+use crate::lexer;
+
+// A stretch of source text on a single line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl Span {
+    pub fn at_token(token: &lexer::Token, length: usize) -> Self {
+        Self {
+            line: token.line(),
+            column: token.column(),
+            length,
+        }
+    }
+
+    // The source line of the span with the span marked underneath, e.g.
+    //
+    //   |
+    // 3 | si 1 delt på 0.
+    //   |      ^^^^^^^
+    pub fn excerpt(&self, source: &str) -> String {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let Some(source_line) = source.lines().nth(self.line.saturating_sub(1)) else {
+            return String::new();
+        };
+        let mut output = format!("{} |\n", gutter);
+        output += &format!("{} | {}\n", line_number, source_line);
+        output += &format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(self.length.max(1))
+        );
+        output
+    }
+}
+
+// A file brought in with `bruk`, for showing what went wrong in it against
+// its own source rather than that of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub path: String,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub help: Option<String>,
+    pub module: Option<Module>,
+    // Something that is likely a mistake but does not stop the program.
+    pub warning: bool,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, line: usize, column: usize, length: usize) -> Self {
+        Self {
+            message,
+            line,
+            column,
+            length,
+            help: None,
+            module: None,
+            warning: false,
+        }
+    }
+
+    pub fn at_token(message: String, token: &lexer::Token, length: usize) -> Self {
+        Self::new(message, token.line(), token.column(), length)
+    }
+
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    pub fn as_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
+
+    // Formats the diagnostic together with the offending source line, e.g.
+    //
+    // feil: `conter` er ikke definert
+    //  --> linje 3, kolonne 11
+    //   |
+    // 3 | la a være conter pluss 1.
+    //   |           ^^^^^^
+    //   = hjelp: mente du `counter`?
+    pub fn render(&self, source: &str) -> String {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let kind = if self.warning { "advarsel" } else { "feil" };
+        let mut output = format!("{}: {}\n", kind, self.message);
+        let (file, source) = match &self.module {
+            Some(module) => (format!("{}, ", module.path), module.source.as_str()),
+            None => (String::new(), source),
+        };
+        output += &format!(
+            "{}--> {}linje {}, kolonne {}\n",
+            gutter, file, self.line, self.column
+        );
+        let span = Span {
+            line: self.line,
+            column: self.column,
+            length: self.length,
+        };
+        output += &span.excerpt(source);
+        if let Some(help) = &self.help {
+            output += &format!("{} = hjelp: {}\n", gutter, help);
+        }
+        output
+    }
+}
@@ -0,0 +1,128 @@
+use std::{cmp::Ordering, fmt};
+
+use crate::bigint::BigInt;
+
+// An exact fraction, produced by `delt på` when the simulator divides exactly.
+// It is always reduced with a positive denominator, so equal fractions look
+// the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fraction {
+    numerator: BigInt,
+    denominator: BigInt,
+}
+
+impl Fraction {
+    // The denominator must not be zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Self {
+        let divisor = numerator.gcd(&denominator);
+        let (mut numerator, mut denominator) = (
+            numerator.div_mod_floor(&divisor).0,
+            denominator.div_mod_floor(&divisor).0,
+        );
+        if denominator.is_negative() {
+            numerator = numerator.negate();
+            denominator = denominator.negate();
+        }
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    pub fn from_big_int(value: BigInt) -> Self {
+        Self {
+            numerator: value,
+            denominator: BigInt::from_i64(1),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    // The fraction as a whole number, if it is one.
+    pub fn as_big_int(&self) -> Option<BigInt> {
+        (self.denominator == BigInt::from_i64(1)).then(|| self.numerator.clone())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator.to_f64() / self.denominator.to_f64()
+    }
+
+    pub fn add(&self, other: &Fraction) -> Fraction {
+        Self::new(
+            self.numerator
+                .mul(&other.denominator)
+                .add(&other.numerator.mul(&self.denominator)),
+            self.denominator.mul(&other.denominator),
+        )
+    }
+
+    pub fn sub(&self, other: &Fraction) -> Fraction {
+        self.add(&other.negate())
+    }
+
+    pub fn mul(&self, other: &Fraction) -> Fraction {
+        Self::new(
+            self.numerator.mul(&other.numerator),
+            self.denominator.mul(&other.denominator),
+        )
+    }
+
+    // The divisor must not be zero.
+    pub fn div(&self, other: &Fraction) -> Fraction {
+        self.mul(&other.reciprocal())
+    }
+
+    // The largest whole number not above the fraction.
+    pub fn floor(&self) -> BigInt {
+        self.numerator.div_mod_floor(&self.denominator).0
+    }
+
+    pub fn abs(&self) -> Fraction {
+        Self::new(self.numerator.abs(), self.denominator.clone())
+    }
+
+    // The whole part of the fraction, rounding towards zero.
+    pub fn truncate(&self) -> BigInt {
+        let whole = self.numerator.abs().div_mod_floor(&self.denominator).0;
+        if self.numerator.is_negative() {
+            whole.negate()
+        } else {
+            whole
+        }
+    }
+
+    pub fn pow(&self, exponent: u32) -> Fraction {
+        Self::new(self.numerator.pow(exponent), self.denominator.pow(exponent))
+    }
+
+    // The fraction must not be zero.
+    pub fn reciprocal(&self) -> Fraction {
+        Self::new(self.denominator.clone(), self.numerator.clone())
+    }
+
+    fn negate(&self) -> Fraction {
+        Self::new(self.numerator.negate(), self.denominator.clone())
+    }
+}
+
+impl Ord for Fraction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numerator
+            .mul(&other.denominator)
+            .cmp(&other.numerator.mul(&self.denominator))
+    }
+}
+
+impl PartialOrd for Fraction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 
-use crate::symbol::{self};
+use crate::{bigint::BigInt, symbol};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     Si,
     La,
+    Alltid,
     Være,
     Er,
     Lik,
@@ -16,24 +17,69 @@ pub enum TokenType {
     Ellers,
     Og,
     Dersom,
+    For,
+    Hvert,
     Gjør,
     Følgende,
     Colon,
     SemiColon,
     Comma,
     Mekanisme,
+    Struktur,
+    Har,
+    Ny,
     Utfør,
+    Gi,
+    Tar,
+    Med,
+    Som,
+    Et,
+    En,
+    Liste,
+    Oppslag,
+    Tomt,
+    Finnes,
+    Tom,
+    Element,
+    Legg,
+    Til,
+    Lengden,
+    Av,
     Exclamation,
     Pluss,
     Minus,
     Ganger,
     Delt,
     På,
+    Rest,
+    Kvotienten,
+    Opphøyd,
+    Svaret,
+    Les,
+    Filen,
+    Linjene,
+    Prøv,
+    Hvis,
+    Feiler,
+    Feil,
+    Påstå,
+    Bruk,
+    Fra,
+    Så,
+    Lenge,
+    Avbryt,
+    Hopp,
+    Videre,
+    Velg,
+    Tilfelle,
     StringLiteral(String),
     StringListIndex(usize),
     Symbol(symbol::SymbolRef),
     Identifier(String),
     IntLiteral(i64),
+    // A whole number written out that does not fit in an `IntLiteral`.
+    BigIntLiteral(BigInt),
+    BoolLiteral(bool),
     FloatLiteral(f64),
     Dot,
     EOF,
@@ -71,6 +117,8 @@ impl Token {
 pub struct Lexer {
     input: String,
     position: usize,
+    // Where the last token began.
+    start: usize,
     line: usize,
     column: usize,
 }
@@ -80,41 +128,51 @@ impl Lexer {
         Self {
             input,
             position: 0,
+            start: 0,
             line: 1,
             column: 1,
         }
     }
 
+    // Numbers the lines from `line` instead of 1, see `module::Sources`.
+    pub fn starting_at_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+
     pub fn lex(&mut self) -> Token {
         self.skip_whitespace();
+        self.start = self.position;
+        let line = self.line;
+        let column = self.column;
 
         if self.peek() == '\0' {
-            return Token::new(TokenType::EOF, self.line, self.column);
+            return Token::new(TokenType::EOF, line, column);
         }
 
         if self.peek() == '.' {
             self.advance();
-            return Token::new(TokenType::Dot, self.line, self.column);
+            return Token::new(TokenType::Dot, line, column);
         }
 
         if self.peek() == ':' {
             self.advance();
-            return Token::new(TokenType::Colon, self.line, self.column);
+            return Token::new(TokenType::Colon, line, column);
         }
 
         if self.peek() == ';' {
             self.advance();
-            return Token::new(TokenType::SemiColon, self.line, self.column);
+            return Token::new(TokenType::SemiColon, line, column);
         }
 
         if self.peek() == ',' {
             self.advance();
-            return Token::new(TokenType::Comma, self.line, self.column);
+            return Token::new(TokenType::Comma, line, column);
         }
 
         if self.peek() == '!' {
             self.advance();
-            return Token::new(TokenType::Exclamation, self.line, self.column);
+            return Token::new(TokenType::Exclamation, line, column);
         }
 
         if self.peek() == '\"' {
@@ -125,11 +183,7 @@ impl Lexer {
             }
             let end = self.position;
             self.advance();
-            return Token::new(
-                TokenType::StringLiteral(self.get(start, end)),
-                self.line,
-                self.column,
-            );
+            return Token::new(TokenType::StringLiteral(self.get(start, end)), line, column);
         }
 
         if self.peek().is_numeric() {
@@ -144,23 +198,24 @@ impl Lexer {
                 }
                 let end = self.position;
                 return Token::new(
-                    TokenType::FloatLiteral(self.input[start..end].parse().unwrap()),
-                    self.line,
-                    self.column,
+                    TokenType::FloatLiteral(self.get(start, end).parse().unwrap()),
+                    line,
+                    column,
                 );
             }
-            let end = self.position;
-            return Token::new(
-                TokenType::IntLiteral(self.get(start, end).parse().unwrap()),
-                self.line,
-                self.column,
-            );
+            let digits = self.get(start, self.position);
+            let token_type = match digits.parse() {
+                Ok(value) => TokenType::IntLiteral(value),
+                Err(_) => TokenType::BigIntLiteral(BigInt::from_digits(&digits)),
+            };
+            return Token::new(token_type, line, column);
         }
 
         // map string to type
         let keywords: HashMap<&str, TokenType> = [
             ("si", TokenType::Si),
             ("la", TokenType::La),
+            ("alltid", TokenType::Alltid),
             ("være", TokenType::Være),
             ("er", TokenType::Er),
             ("lik", TokenType::Lik),
@@ -171,21 +226,68 @@ impl Lexer {
             ("og", TokenType::Og),
             ("eller", TokenType::Eller),
             ("dersom", TokenType::Dersom),
+            ("for", TokenType::For),
+            ("hvert", TokenType::Hvert),
             ("gjør", TokenType::Gjør),
             ("følgende", TokenType::Følgende),
             ("mekanisme", TokenType::Mekanisme),
+            ("struktur", TokenType::Struktur),
+            ("har", TokenType::Har),
+            ("ny", TokenType::Ny),
             ("utfør", TokenType::Utfør),
+            ("gi", TokenType::Gi),
+            ("tar", TokenType::Tar),
+            ("sant", TokenType::BoolLiteral(true)),
+            ("usant", TokenType::BoolLiteral(false)),
+            ("med", TokenType::Med),
+            ("som", TokenType::Som),
+            ("et", TokenType::Et),
+            ("en", TokenType::En),
+            ("liste", TokenType::Liste),
+            ("oppslag", TokenType::Oppslag),
+            ("tomt", TokenType::Tomt),
+            ("finnes", TokenType::Finnes),
+            ("tom", TokenType::Tom),
+            ("element", TokenType::Element),
+            ("legg", TokenType::Legg),
+            ("til", TokenType::Til),
+            ("lengden", TokenType::Lengden),
+            ("av", TokenType::Av),
             ("pluss", TokenType::Pluss),
             ("minus", TokenType::Minus),
             ("ganger", TokenType::Ganger),
             ("delt", TokenType::Delt),
             ("på", TokenType::På),
+            ("rest", TokenType::Rest),
+            ("kvotienten", TokenType::Kvotienten),
+            ("opphøyd", TokenType::Opphøyd),
+            ("svaret", TokenType::Svaret),
+            ("les", TokenType::Les),
+            ("filen", TokenType::Filen),
+            ("linjene", TokenType::Linjene),
+            ("prøv", TokenType::Prøv),
+            ("hvis", TokenType::Hvis),
+            ("feiler", TokenType::Feiler),
+            ("feil", TokenType::Feil),
+            ("påstå", TokenType::Påstå),
+            ("bruk", TokenType::Bruk),
+            ("fra", TokenType::Fra),
+            ("så", TokenType::Så),
+            ("lenge", TokenType::Lenge),
+            ("avbryt", TokenType::Avbryt),
+            ("hopp", TokenType::Hopp),
+            ("videre", TokenType::Videre),
+            ("velg", TokenType::Velg),
+            ("tilfelle", TokenType::Tilfelle),
         ]
         .iter()
         .cloned()
         .collect();
 
         if self.peek().is_alphabetic() {
+            if let Some(value) = self.number_words() {
+                return Token::new(TokenType::IntLiteral(value), line, column);
+            }
             let start = self.position;
             while self.peek().is_alphabetic() {
                 self.advance();
@@ -198,19 +300,98 @@ impl Lexer {
                 .take(end - start)
                 .collect::<String>();
             if let Some(token_type) = keywords.get(&identifier[..]) {
-                return Token::new(token_type.clone(), self.line, self.column);
+                return Token::new(token_type.clone(), line, column);
             }
-            return Token::new(TokenType::Identifier(identifier), self.line, self.column);
+            return Token::new(TokenType::Identifier(identifier), line, column);
         }
 
         panic!("Unexpected character: {}", self.peek());
     }
 
+    // Reads a number written with words, such as `førtifem` or `tre hundre og
+    // tolv`, as long a number as the following words make. A number may be
+    // split over several words or written as one, `trehundreogtolv`.
+    //
+    // `og` also separates the last elements of a list, so it only joins a
+    // number when it comes right after `hundre`, `tusen`, `million` or
+    // `milliard` and right before a number below a hundred: `liste med tre
+    // hundre og tolv` is a list of one number, 312, while `liste med tre
+    // hundre og 12` and `liste med fem og tolv` are lists of two. On their own
+    // `en` and `et` are articles, they are only numbers before a word like
+    // `hundre`, as in `en million`, or after `og` and another number, as in
+    // `liste med tjue og en`.
+    fn number_words(&mut self) -> Option<i64> {
+        let start = (self.position, self.line, self.column);
+        let mut words = Vec::new();
+        while self.peek().is_alphabetic() {
+            let word_start = self.position;
+            while self.peek().is_alphabetic() {
+                self.advance();
+            }
+            let word = self.get(word_start, self.position);
+            let splits = number_morphemes(&word);
+            if splits.is_empty() {
+                break;
+            }
+            words.push((word, splits, (self.position, self.line, self.column)));
+            self.skip_whitespace();
+        }
+
+        for count in (1..=words.len()).rev() {
+            if count == 1
+                && (words[0].0 == "en" || words[0].0 == "et")
+                && !self.follows_number_and(start.0)
+            {
+                continue;
+            }
+            let mut candidates = vec![Vec::new()];
+            for (_, splits, _) in &words[..count] {
+                candidates = candidates
+                    .iter()
+                    .flat_map(|candidate| {
+                        splits
+                            .iter()
+                            .map(move |split| [&candidate[..], &split[..]].concat())
+                    })
+                    .collect();
+            }
+            let value = candidates.iter().find_map(|morphemes| {
+                let mut parser = NumberParser {
+                    morphemes,
+                    position: 0,
+                };
+                let value = parser.number()?;
+                (parser.position == morphemes.len()).then_some(value)
+            });
+            if let Some(value) = value {
+                (self.position, self.line, self.column) = words[count - 1].2;
+                return Some(value);
+            }
+        }
+        (self.position, self.line, self.column) = start;
+        None
+    }
+
+    // Whether the words before `position` are a number word and `og`.
+    fn follows_number_and(&self, position: usize) -> bool {
+        let before = self.get(0, position);
+        let mut words = before.split_whitespace().rev();
+        words.next() == Some("og")
+            && words
+                .next()
+                .is_some_and(|word| !number_morphemes(word).is_empty())
+    }
+
+    // The source text of the last token, e.g. `tre hundre` for the number 300.
+    pub fn last_text(&self) -> String {
+        self.get(self.start, self.position)
+    }
+
     fn skip_whitespace(&mut self) {
         while self.peek() == ' ' || self.peek() == '\n' || self.peek() == '\t' {
             if self.peek() == '\n' {
                 self.line += 1;
-                self.column = 1;
+                self.column = 0;
             }
             self.advance();
         }
@@ -236,6 +417,167 @@ impl Lexer {
     }
 }
 
+// The parts number words are made of, `førtifem` is `førti` and `fem`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Morpheme {
+    Zero,
+    // From 1 to 19.
+    Unit(i64),
+    Tens(i64),
+    Hundred,
+    // A thousand, a million or a billion.
+    Scale(i64),
+    And,
+}
+
+const NUMBER_WORDS: [(&str, Morpheme); 41] = [
+    ("null", Morpheme::Zero),
+    ("en", Morpheme::Unit(1)),
+    ("én", Morpheme::Unit(1)),
+    ("et", Morpheme::Unit(1)),
+    ("ett", Morpheme::Unit(1)),
+    ("to", Morpheme::Unit(2)),
+    ("tre", Morpheme::Unit(3)),
+    ("fire", Morpheme::Unit(4)),
+    ("fem", Morpheme::Unit(5)),
+    ("seks", Morpheme::Unit(6)),
+    ("sju", Morpheme::Unit(7)),
+    ("syv", Morpheme::Unit(7)),
+    ("åtte", Morpheme::Unit(8)),
+    ("ni", Morpheme::Unit(9)),
+    ("ti", Morpheme::Unit(10)),
+    ("elleve", Morpheme::Unit(11)),
+    ("tolv", Morpheme::Unit(12)),
+    ("tretten", Morpheme::Unit(13)),
+    ("fjorten", Morpheme::Unit(14)),
+    ("femten", Morpheme::Unit(15)),
+    ("seksten", Morpheme::Unit(16)),
+    ("sytten", Morpheme::Unit(17)),
+    ("atten", Morpheme::Unit(18)),
+    ("nitten", Morpheme::Unit(19)),
+    ("tjue", Morpheme::Tens(20)),
+    ("tyve", Morpheme::Tens(20)),
+    ("tretti", Morpheme::Tens(30)),
+    ("tredve", Morpheme::Tens(30)),
+    ("førti", Morpheme::Tens(40)),
+    ("femti", Morpheme::Tens(50)),
+    ("seksti", Morpheme::Tens(60)),
+    ("sytti", Morpheme::Tens(70)),
+    ("åtti", Morpheme::Tens(80)),
+    ("nitti", Morpheme::Tens(90)),
+    ("hundre", Morpheme::Hundred),
+    ("tusen", Morpheme::Scale(1_000)),
+    ("million", Morpheme::Scale(1_000_000)),
+    ("millioner", Morpheme::Scale(1_000_000)),
+    ("milliard", Morpheme::Scale(1_000_000_000)),
+    ("milliarder", Morpheme::Scale(1_000_000_000)),
+    ("og", Morpheme::And),
+];
+
+// Every way to split a word into number parts, none if it is not a number
+// word. Some words split more than one way, `femti` is also `fem` and `ti`,
+// so it is up to `NumberParser` to pick the one that makes a number.
+fn number_morphemes(word: &str) -> Vec<Vec<Morpheme>> {
+    if word.is_empty() {
+        return vec![Vec::new()];
+    }
+    let mut splits = Vec::new();
+    for (prefix, morpheme) in NUMBER_WORDS {
+        let Some(rest) = word.strip_prefix(prefix) else {
+            continue;
+        };
+        for rest in number_morphemes(rest) {
+            let mut morphemes = vec![morpheme];
+            morphemes.extend(rest);
+            splits.push(morphemes);
+        }
+    }
+    splits
+}
+
+// Works out the value of a sequence of number parts, largest scale first,
+// e.g. `to millioner tre hundre og tolv tusen og fem`.
+struct NumberParser<'a> {
+    morphemes: &'a [Morpheme],
+    position: usize,
+}
+
+impl NumberParser<'_> {
+    fn peek(&self) -> Option<Morpheme> {
+        self.morphemes.get(self.position).copied()
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        if self.peek() == Some(Morpheme::Zero) {
+            self.position += 1;
+            return Some(0);
+        }
+        let mut total = 0;
+        let mut found = false;
+        for scale in [1_000_000_000, 1_000_000, 1_000] {
+            let start = self.position;
+            let count = self.below_thousand();
+            if self.peek() != Some(Morpheme::Scale(scale)) {
+                self.position = start;
+                continue;
+            }
+            self.position += 1;
+            total += count.unwrap_or(1) * scale;
+            found = true;
+            if let Some(rest) = self.and_below_hundred() {
+                return Some(total + rest);
+            }
+        }
+        match self.below_thousand() {
+            Some(rest) => Some(total + rest),
+            None => found.then_some(total),
+        }
+    }
+
+    fn below_thousand(&mut self) -> Option<i64> {
+        let count = self.below_hundred();
+        if self.peek() != Some(Morpheme::Hundred) {
+            return count;
+        }
+        self.position += 1;
+        let rest = self
+            .and_below_hundred()
+            .or_else(|| self.below_hundred())
+            .unwrap_or(0);
+        Some(count.unwrap_or(1) * 100 + rest)
+    }
+
+    // `og` and a number below a hundred, as in `hundre og tolv`.
+    fn and_below_hundred(&mut self) -> Option<i64> {
+        let next = self.morphemes.get(self.position + 1);
+        if self.peek() != Some(Morpheme::And)
+            || !matches!(next, Some(Morpheme::Unit(_) | Morpheme::Tens(_)))
+        {
+            return None;
+        }
+        self.position += 1;
+        self.below_hundred()
+    }
+
+    fn below_hundred(&mut self) -> Option<i64> {
+        match self.peek()? {
+            Morpheme::Unit(unit) => {
+                self.position += 1;
+                Some(unit)
+            }
+            Morpheme::Tens(tens) => {
+                self.position += 1;
+                if let Some(Morpheme::Unit(unit @ 1..=9)) = self.peek() {
+                    self.position += 1;
+                    return Some(tens + unit);
+                }
+                Some(tens)
+            }
+            _ => None,
+        }
+    }
+}
+
 impl Iterator for Lexer {
     type Item = Token;
 
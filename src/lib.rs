@@ -0,0 +1,135 @@
+#![allow(clippy::upper_case_acronyms, clippy::vec_box)]
+
+use std::{error::Error, path::Path};
+
+use tracing::info;
+
+pub use crate::{
+    console::{Capture, Console, Terminal},
+    diagnostic::Diagnostic,
+    simulate::{Division, Options, RuntimeError},
+};
+
+mod ast;
+mod bigint;
+mod builtins;
+mod cfg;
+mod console;
+mod diagnostic;
+mod fraction;
+mod lexer;
+mod module;
+mod optimizer;
+mod parser;
+mod simulate;
+mod symbol;
+mod types;
+mod utils;
+mod value;
+
+// A program that has passed every check and is ready to run.
+pub struct Program {
+    tree: Box<ast::Node>,
+    symbol_table: symbol::SymbolTable,
+    string_list: symbol::StringList,
+    cfg: cfg::CFG,
+    sources: module::Sources,
+    warnings: Vec<Diagnostic>,
+}
+
+// Runs every pass up to the optimized control flow graph, stopping after the
+// first pass that finds something wrong. Warnings only stop it together with
+// an error. Files named by `bruk` are looked for
+// in the current directory.
+pub fn compile(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    compile_source(source, None)
+}
+
+// Like `compile`, for a source read from `path`, so that `bruk` finds files
+// next to it.
+pub fn compile_file(path: &Path, source: &str) -> Result<Program, Vec<Diagnostic>> {
+    compile_source(source, Some(path))
+}
+
+fn compile_source(source: &str, path: Option<&Path>) -> Result<Program, Vec<Diagnostic>> {
+    info!("Lexical and syntactic analysis");
+    let lexer = lexer::Lexer::new(source.to_string());
+    let mut parser = parser::Parser::new(lexer);
+    let mut tree = parser.parse();
+    let mut sources = module::Sources::new(source);
+    let diagnostics = parser.take_diagnostics();
+    if !diagnostics.is_empty() {
+        return Err(sources.locate_diagnostics(diagnostics));
+    }
+
+    info!("Semantic analysis");
+    ast::simplify_tree(&mut tree);
+
+    info!("Loading modules");
+    let diagnostics = module::load(&mut tree, path, &mut sources);
+    if !diagnostics.is_empty() {
+        return Err(sources.locate_diagnostics(diagnostics));
+    }
+
+    info!("Building string list");
+    let mut string_list = symbol::StringList::new();
+    ast::fill_string_list(&mut tree, &mut string_list);
+
+    info!("Building symbol table");
+    let mut symbol_table = symbol::SymbolTable::new();
+    let diagnostics = ast::find_symbols(&mut tree, &mut symbol_table);
+    if !diagnostics.is_empty() {
+        return Err(sources.locate_diagnostics(diagnostics));
+    }
+
+    info!("Inferring types");
+    let diagnostics = sources.locate_diagnostics(types::infer_types(&mut tree, &mut symbol_table));
+    if diagnostics.iter().any(|diagnostic| !diagnostic.warning) {
+        return Err(diagnostics);
+    }
+
+    info!("Building control flow graph");
+    let mut cfg = cfg::CFG::new();
+    cfg.build(&tree);
+
+    info!("Optimizing control flow graph");
+    optimizer::optimize(&mut cfg, &symbol_table);
+
+    Ok(Program {
+        tree,
+        symbol_table,
+        string_list,
+        cfg,
+        sources,
+        warnings: diagnostics,
+    })
+}
+
+impl Program {
+    // Writes the syntax tree, symbol table, string list and control flow graph
+    // to the current directory for inspection.
+    pub fn write_files(&self) -> Result<(), Box<dyn Error>> {
+        info!("Writing files");
+        self.tree.write_to_file("ast.png")?;
+        self.symbol_table.write_to_file("symbol_table.txt")?;
+        self.string_list.write_to_file("string_list.txt")?;
+        self.cfg.write_to_graphwiz("cfg.png")?;
+        Ok(())
+    }
+
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn run(&self, options: Options, console: &mut dyn Console) -> Result<(), RuntimeError> {
+        info!("Simulating");
+        simulate::simulate_cfg(
+            &self.cfg,
+            &self.symbol_table,
+            &self.string_list,
+            options,
+            console,
+        )
+        .map_err(|error| self.sources.locate_error(error))
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    ast::{self, Node, NodeType},
+    diagnostic::{Diagnostic, Module},
+    lexer::Lexer,
+    parser::Parser,
+    simulate::RuntimeError,
+    types,
+};
+
+// Every file of a program. The files brought in with `bruk` are lexed with
+// their lines numbered on from the end of the file before them, so a line
+// number on its own tells which file a token is in. Diagnostics and runtime
+// errors are turned back into lines of their own file on the way out.
+pub struct Sources {
+    next_line: usize,
+    modules: Vec<(usize, Module)>,
+}
+
+impl Sources {
+    pub fn new(source: &str) -> Self {
+        Self {
+            next_line: 1 + line_count(source),
+            modules: Vec::new(),
+        }
+    }
+
+    // Adds a file and gives the number its first line gets.
+    fn add(&mut self, path: String, source: String) -> usize {
+        let first_line = self.next_line;
+        self.next_line += line_count(&source);
+        self.modules.push((first_line, Module { path, source }));
+        first_line
+    }
+
+    // The module a line is in, unless it is in the program itself, and the
+    // line within that file.
+    fn locate(&self, line: usize) -> (Option<&Module>, usize) {
+        match self
+            .modules
+            .iter()
+            .rev()
+            .find(|(first_line, _)| line >= *first_line)
+        {
+            Some((first_line, module)) => (Some(module), line - first_line + 1),
+            None => (None, line),
+        }
+    }
+
+    pub fn locate_diagnostics(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                let (module, line) = self.locate(diagnostic.line);
+                diagnostic.line = line;
+                diagnostic.module = module.cloned();
+                diagnostic
+            })
+            .collect()
+    }
+
+    pub fn locate_error(&self, mut error: RuntimeError) -> RuntimeError {
+        if let Some(span) = &mut error.span {
+            let (module, line) = self.locate(span.line);
+            span.line = line;
+            error.module = module.cloned();
+        }
+        error
+    }
+}
+
+fn line_count(source: &str) -> usize {
+    source.split('\n').count()
+}
+
+// Folds away `.` and `..` where it can without looking at the disk, so that
+// `lib/../hjelp.nb` is shown as `hjelp.nb`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Loads the file named by every `bruk` in `tree` and puts its statements
+// under the `bruk`, so the later passes see a single tree. Paths are relative
+// to the file the `bruk` is in, which for the program is `file`. A file is
+// only loaded the first time it is used, later uses are left empty and share
+// what the first one declared, see `ast::declare_module`.
+pub fn load(tree: &mut Box<Node>, file: Option<&Path>, sources: &mut Sources) -> Vec<Diagnostic> {
+    let mut loader = Loader {
+        sources,
+        loaded: Vec::new(),
+        stack: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let directory = file.and_then(Path::parent).unwrap_or(Path::new(""));
+    if let Some(file) = file {
+        if let Ok(canonical) = file.canonicalize() {
+            loader.stack.push((canonical, file.display().to_string()));
+        }
+    }
+    loader.load_imports(tree, directory);
+    loader.diagnostics
+}
+
+struct Loader<'a> {
+    sources: &'a mut Sources,
+    loaded: Vec<PathBuf>,
+    // The files being loaded, innermost last, by their canonical path and the
+    // path they are shown with. A file that uses one of these goes in a ring.
+    stack: Vec<(PathBuf, String)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader<'_> {
+    fn load_imports(&mut self, node: &mut Box<Node>, directory: &Path) {
+        if node.node_type == NodeType::Import {
+            self.load_module(node, directory);
+            return;
+        }
+        for child in &mut node.children {
+            self.load_imports(child, directory);
+        }
+    }
+
+    fn load_module(&mut self, node: &mut Box<Node>, directory: &Path) {
+        let token = node.token.clone().unwrap();
+        let path = normalize(&directory.join(&node.data));
+        let shown = path.display().to_string();
+        let Ok(canonical) = path.canonicalize() else {
+            self.diagnostics.push(Diagnostic::at_token(
+                format!("finner ikke filen \"{}\"", shown),
+                &token,
+                4,
+            ));
+            return;
+        };
+        if let Some(start) = self.stack.iter().position(|(file, _)| *file == canonical) {
+            let mut ring: Vec<&str> = self.stack[start..]
+                .iter()
+                .map(|(_, shown)| shown.as_str())
+                .collect();
+            ring.push(&shown);
+            self.diagnostics.push(Diagnostic::at_token(
+                format!("filene bruker hverandre i ring: {}", ring.join(" → ")),
+                &token,
+                4,
+            ));
+            return;
+        }
+        node.data = canonical.display().to_string();
+        if self.loaded.contains(&canonical) {
+            return;
+        }
+        self.loaded.push(canonical.clone());
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => {
+                self.diagnostics.push(Diagnostic::at_token(
+                    format!("kunne ikke lese filen \"{}\": {}", shown, error),
+                    &token,
+                    4,
+                ));
+                return;
+            }
+        };
+        let first_line = self.sources.add(shown.clone(), source.clone());
+        let mut parser = Parser::new(Lexer::new(source).starting_at_line(first_line));
+        let mut tree = parser.parse();
+        self.diagnostics.extend(parser.take_diagnostics());
+        ast::simplify_tree(&mut tree);
+        let mut statements = match tree.node_type {
+            NodeType::StatementList => tree.children,
+            _ => vec![tree],
+        };
+
+        // A module only gives mechanisms to the file using it, it has nothing
+        // of its own to run.
+        for statement in &statements {
+            if let NodeType::FunctionDefinition | NodeType::Import = statement.node_type {
+                continue;
+            }
+            if let Some(token) = statement.token.as_ref().or(types::first_token(statement)) {
+                self.diagnostics.push(Diagnostic::at_token(
+                    "en modul kan bare ha mekanismer og `bruk`".to_string(),
+                    token,
+                    1,
+                ));
+            }
+        }
+
+        let module_directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.stack.push((canonical, shown));
+        for statement in &mut statements {
+            self.load_imports(statement, &module_directory);
+        }
+        self.stack.pop();
+        node.children = statements;
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use crate::{
+    cfg::{Opcode, Operand, CFG},
+    symbol::{SymbolKind, SymbolRef, SymbolTable},
+};
+
+pub fn optimize(cfg: &mut CFG, symbol_table: &SymbolTable) {
+    inline_constants(cfg, symbol_table);
+}
+
+fn is_literal(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Immediate(_)
+            | Operand::BigInt(_)
+            | Operand::Float(_)
+            | Operand::Bool(_)
+            | Operand::String(_)
+    )
+}
+
+// Replaces every read of a constant that was given a literal value with the
+// literal itself. Constants are never reassigned, and the temporaries they are
+// set from are assigned only once, so the value seen at the definition holds
+// everywhere.
+fn inline_constants(cfg: &mut CFG, symbol_table: &SymbolTable) {
+    let mut literals: HashMap<usize, Operand> = HashMap::new();
+    for instruction in cfg.instructions() {
+        if instruction.opcode != Opcode::Set {
+            continue;
+        }
+        if let Operand::Temporary(temporary_id) = &instruction.operands[0] {
+            if is_literal(&instruction.operands[1]) {
+                literals.insert(*temporary_id, instruction.operands[1].clone());
+            }
+        }
+    }
+
+    let mut constants: HashMap<SymbolRef, Operand> = HashMap::new();
+    for instruction in cfg.instructions() {
+        if instruction.opcode != Opcode::Set {
+            continue;
+        }
+        if let (Operand::Variable(symbol), Operand::Temporary(temporary_id)) =
+            (&instruction.operands[0], &instruction.operands[1])
+        {
+            if symbol_table.get(*symbol).kind != SymbolKind::Constant {
+                continue;
+            }
+            if let Some(literal) = literals.get(temporary_id) {
+                constants.insert(*symbol, literal.clone());
+            }
+        }
+    }
+
+    for instruction in cfg.instructions_mut() {
+        // The first operand of these is written, not read.
+        let first_read = match instruction.opcode {
+            Opcode::Set | Opcode::Pop | Opcode::Catch => 1,
+            _ => 0,
+        };
+        for operand in instruction.operands.iter_mut().skip(first_read) {
+            if let Operand::Variable(symbol) = operand {
+                if let Some(literal) = constants.get(symbol) {
+                    *operand = literal.clone();
+                }
+            }
+        }
+    }
+}
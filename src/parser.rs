@@ -1,46 +1,124 @@
-use tracing::{error, info};
+use tracing::error;
 
 use crate::{
     ast::{Node, NodeType},
+    diagnostic::Diagnostic,
     lexer::{Lexer, Token},
 };
 
 // program -> statement_list
 // statement_list -> statement . statement_list | ε
-// statement -> print_statement statement_tail | assignment_statement statement_tail | if_statement statement_tail | function_definition | function_call
+// statement -> print_statement statement_tail | assignment_statement statement_tail | if_statement statement_tail | for_statement statement_tail | while_statement statement_tail | switch_statement statement_tail | loop_control statement_tail | return_statement statement_tail | append_statement statement_tail | write_statement statement_tail | try_statement statement_tail | raise_statement statement_tail | assert_statement statement_tail | function_definition | struct_definition | function_call | import_statement
 // statement_tail -> , statement | ε
-// print_statement -> si expression
-// if_statement -> dersom condition gjør følgende: statement
-// assignment_statement -> la identifier være expression
+// print_statement -> si value
+// return_statement -> gi value
+// append_statement -> legg value til factor | legg value som value til factor
+// if_statement -> dersom value gjør følgende: statement
+// for_statement -> for hvert identifier i value gjør følgende: statement
+// while_statement -> så lenge value gjør følgende: statement
+// loop_control -> avbryt | hopp videre
+// switch_statement -> velg value : case case_tail .
+// case_tail -> , case case_tail | ε
+// case -> tilfelle expression case_value_tail gjør følgende: statement | ellers gjør følgende: statement
+// case_value_tail -> , expression case_value_tail | og expression | ε
+// assignment_statement -> la identifier constant_marker være value type_annotation | la field_access være value
+// constant_marker -> alltid | ε
+// type_annotation -> som article identifier | ε
+// article -> et | en
+// value -> expression condition_tail
+// condition_tail -> er comparator expression | ε
 // expression -> term expression_tail
 // expression_tail -> + term expression_tail | - term expression_tail | ε
-// term -> factor term_tail
-// term_tail -> * factor term_tail | / factor term_tail | ε
-// factor -> ( expression ) | identifier | int_literal | float_literal | function_call | - factor | string_literal
-// function_definition -> mekanisme identifier gjør følgende : statement
-// function_call -> utfør identifier !
+// term -> power term_tail
+// term_tail -> * power term_tail | / power term_tail | ε
+// power -> factor opphøyd i power | factor
+// factor -> ( expression ) | identifier | int_literal | float_literal | bool_literal | function_call | minus factor | string_literal | remainder | quotient | list_literal | element | list_length | map_literal | contains | struct_literal | field_access | read | file_read | anonymous_function
+// remainder -> rest av power delt på power
+// quotient -> kvotienten av power delt på power
+// list_literal -> liste med expression list_tail | tom liste
+// list_tail -> , expression list_tail | og expression | ε
+// element -> element expression i factor
+// list_length -> lengden av factor
+// map_literal -> oppslag med map_entry map_entry_tail | tomt oppslag
+// map_entry_tail -> , map_entry map_entry_tail | og map_entry | ε
+// map_entry -> expression som expression
+// contains -> finnes expression i factor
+// struct_definition -> struktur identifier har field field_list_tail
+// field_list_tail -> , field field_list_tail | og field | ε
+// field -> article identifier identifier | article identifier | identifier
+// struct_literal -> ny identifier med field_value field_value_tail | ny identifier
+// field_value_tail -> , field_value field_value_tail | og field_value | ε
+// field_value -> identifier value
+// field_access -> identifier til factor
+// read -> svaret på factor
+// write_statement -> skriv value til filen factor
+// file_read -> les filen factor | les linjene i filen factor
+// try_statement -> prøv gjør følgende: statement . hvis det feiler error_name gjør følgende: statement .
+// error_name -> med identifier | ε
+// raise_statement -> feil med value
+// assert_statement -> påstå value
+// import_statement -> bruk string_literal
+// function_definition -> mekanisme identifier parameter_list function_body
+// function_body -> gjør følgende : statement | : statement
+// parameter_list -> tar parameter parameter_list_tail | ε
+// parameter_list_tail -> , parameter parameter_list_tail | og parameter | ε
+// parameter -> article identifier identifier | identifier
+// function_call -> utfør identifier module_name argument_list !
+// module_name -> fra identifier | ε
+// argument_list -> med value argument_list_tail | ε
+// argument_list_tail -> , value argument_list_tail | og value | ε
+// anonymous_function -> mekanisme som anonymous_parameters gir value
+// anonymous_parameters -> tar parameter anonymous_parameters_tail | ε
+// anonymous_parameters_tail -> , parameter anonymous_parameters_tail | og parameter anonymous_parameters_tail | og | ε
 
 pub struct Parser {
     lexer: Lexer,
     token: Token,
+    // The token after `token`, when it has been looked at already.
+    next: Option<Token>,
+    // Set while parsing the value of `legg x til liste` and `skriv x til
+    // filen`, where `x til` is not a field access.
+    in_append: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
     pub fn new(mut lexer: Lexer) -> Self {
         let token = lexer.lex();
-        Self { lexer, token }
+        Self {
+            lexer,
+            token,
+            next: None,
+            in_append: false,
+            diagnostics: Vec::new(),
+        }
     }
 
     pub fn parse(&mut self) -> Box<Node> {
         self.program()
     }
 
+    // The mistakes found while parsing that did not stop it.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
     fn is_at_end(&self) -> bool {
         *self.token.token_type() == crate::lexer::TokenType::EOF
     }
 
     fn advance(&mut self) {
-        self.token = self.lexer.lex();
+        self.token = match self.next.take() {
+            Some(token) => token,
+            None => self.lexer.lex(),
+        };
+    }
+
+    fn peek(&mut self) -> &Token {
+        if self.next.is_none() {
+            self.next = Some(self.lexer.lex());
+        }
+        self.next.as_ref().unwrap()
     }
 
     fn expect(&mut self, token_type: crate::lexer::TokenType) {
@@ -76,28 +154,66 @@ impl Parser {
         match self.token.token_type() {
             crate::lexer::TokenType::Si => {
                 node.children.push(self.print_statement());
-                if *self.token.token_type() == crate::lexer::TokenType::Comma {
-                    self.advance();
-                    node.children.push(self.statement());
-                }
+                self.statement_tail(&mut node);
             }
             crate::lexer::TokenType::La => {
                 node.children.push(self.assignment_statement());
-                if *self.token.token_type() == crate::lexer::TokenType::Comma {
-                    self.advance();
-                    node.children.push(self.statement());
-                }
+                self.statement_tail(&mut node);
             }
             crate::lexer::TokenType::Dersom => {
                 node.children.push(self.if_statement());
-                if *self.token.token_type() == crate::lexer::TokenType::Comma {
-                    self.advance();
-                    node.children.push(self.statement());
-                }
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::For => {
+                node.children.push(self.for_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Så => {
+                node.children.push(self.while_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Velg => {
+                node.children.push(self.switch_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Avbryt | crate::lexer::TokenType::Hopp => {
+                node.children.push(self.loop_control());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Gi => {
+                node.children.push(self.return_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Legg => {
+                node.children.push(self.append_statement());
+                self.statement_tail(&mut node);
+            }
+            // Not a keyword, so that `skriv` can still name a mechanism.
+            crate::lexer::TokenType::Identifier(name) if name == "skriv" => {
+                node.children.push(self.write_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Prøv => {
+                node.children.push(self.try_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Feil => {
+                node.children.push(self.raise_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Påstå => {
+                node.children.push(self.assert_statement());
+                self.statement_tail(&mut node);
+            }
+            crate::lexer::TokenType::Bruk => {
+                node.children.push(self.import_statement());
             }
             crate::lexer::TokenType::Mekanisme => {
                 node.children.push(self.function_definition());
             }
+            crate::lexer::TokenType::Struktur => {
+                node.children.push(self.struct_definition());
+            }
             crate::lexer::TokenType::Utfør => {
                 node.children.push(self.function_call());
             }
@@ -110,10 +226,65 @@ impl Parser {
         node
     }
 
+    // A comma before `tilfelle` or `ellers` ends the body of a case in `velg`
+    // rather than going on with another statement.
+    fn statement_tail(&mut self, node: &mut Box<Node>) {
+        if *self.token.token_type() != crate::lexer::TokenType::Comma {
+            return;
+        }
+        if matches!(
+            self.peek().token_type(),
+            crate::lexer::TokenType::Tilfelle | crate::lexer::TokenType::Ellers
+        ) {
+            return;
+        }
+        self.advance();
+        node.children.push(self.statement());
+    }
+
     fn print_statement(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::PrintStatement));
         self.expect(crate::lexer::TokenType::Si);
-        node.children.push(self.expression());
+        node.children.push(self.value());
+        node
+    }
+
+    fn return_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ReturnStatement));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Gi);
+        node.children.push(self.value());
+        node
+    }
+
+    fn append_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ListAppend));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Legg);
+        self.in_append = true;
+        node.children.push(self.value());
+        if *self.token.token_type() == crate::lexer::TokenType::Som {
+            self.advance();
+            node.node_type = NodeType::MapInsert;
+            node.children.push(self.value());
+        }
+        self.in_append = false;
+        self.expect(crate::lexer::TokenType::Til);
+        node.children.push(self.factor());
+        node
+    }
+
+    // `skriv "hei" til filen "ut.txt"` replaces what was in the file.
+    fn write_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::FileWrite));
+        node.token = Some(self.token.clone());
+        self.advance();
+        self.in_append = true;
+        node.children.push(self.value());
+        self.in_append = false;
+        self.expect(crate::lexer::TokenType::Til);
+        self.expect(crate::lexer::TokenType::Filen);
+        node.children.push(self.factor());
         node
     }
 
@@ -121,15 +292,54 @@ impl Parser {
         let mut node = Box::new(Node::new(NodeType::AssignmentStatement));
         self.expect(crate::lexer::TokenType::La);
         node.children.push(self.identifier());
+        if *self.token.token_type() == crate::lexer::TokenType::Til {
+            let identifier = node.children.pop().unwrap();
+            node.node_type = NodeType::FieldAssignment;
+            node.children.push(self.field_access(identifier));
+            self.expect(crate::lexer::TokenType::Være);
+            node.children.push(self.value());
+            return node;
+        }
+        if *self.token.token_type() == crate::lexer::TokenType::Alltid {
+            self.advance();
+            node.data = "alltid".to_string();
+        }
         self.expect(crate::lexer::TokenType::Være);
-        node.children.push(self.expression());
+        node.children.push(self.value());
+        if *self.token.token_type() == crate::lexer::TokenType::Som {
+            self.advance();
+            node.children.push(self.type_annotation());
+        }
+        node
+    }
+
+    fn type_annotation(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::TypeAnnotation));
+        match self.token.token_type() {
+            crate::lexer::TokenType::Et | crate::lexer::TokenType::En => self.advance(),
+            _ => panic!(
+                "Expected `et` or `en` before the type but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) => node.data = name.clone(),
+            _ => panic!(
+                "Expected a type but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        node.token = Some(self.token.clone());
+        self.advance();
         node
     }
 
     fn if_statement(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::IfStatement));
         self.expect(crate::lexer::TokenType::Dersom);
-        node.children.push(self.condition());
+        node.children.push(self.value());
         self.expect(crate::lexer::TokenType::Gjør);
         self.expect(crate::lexer::TokenType::Følgende);
         self.expect(crate::lexer::TokenType::Colon);
@@ -146,9 +356,197 @@ impl Parser {
         node
     }
 
-    fn condition(&mut self) -> Box<Node> {
+    fn for_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ForStatement));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::For);
+        self.expect(crate::lexer::TokenType::Hvert);
+        node.children.push(self.identifier());
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) if name == "i" => self.advance(),
+            _ => panic!(
+                "Expected `i` after the loop variable but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        node.children.push(self.value());
+        self.expect(crate::lexer::TokenType::Gjør);
+        self.expect(crate::lexer::TokenType::Følgende);
+        self.expect(crate::lexer::TokenType::Colon);
+        node.children.push(self.statement());
+        self.expect(crate::lexer::TokenType::Dot);
+        node
+    }
+
+    // `prøv gjør følgende: … . hvis det feiler med melding gjør følgende: … .`
+    // The name after `med` is optional and holds the message of the error.
+    fn try_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::TryStatement));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Prøv);
+        self.expect(crate::lexer::TokenType::Gjør);
+        self.expect(crate::lexer::TokenType::Følgende);
+        self.expect(crate::lexer::TokenType::Colon);
+        node.children.push(self.statement());
+        self.expect(crate::lexer::TokenType::Dot);
+        self.expect(crate::lexer::TokenType::Hvis);
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) if name == "det" => self.advance(),
+            _ => panic!(
+                "Expected `det` after `hvis` but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        self.expect(crate::lexer::TokenType::Feiler);
+        let message = match self.token.token_type() {
+            crate::lexer::TokenType::Med => {
+                self.advance();
+                Some(self.identifier())
+            }
+            _ => None,
+        };
+        self.expect(crate::lexer::TokenType::Gjør);
+        self.expect(crate::lexer::TokenType::Følgende);
+        self.expect(crate::lexer::TokenType::Colon);
+        node.children.push(self.statement());
+        self.expect(crate::lexer::TokenType::Dot);
+        node.children.extend(message);
+        node
+    }
+
+    fn raise_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Raise));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Feil);
+        self.expect(crate::lexer::TokenType::Med);
+        node.children.push(self.value());
+        node
+    }
+
+    // `bruk "matte.nb"` names a file whose mechanisms are called as
+    // `utfør kvadrat fra matte med 3!`. The file is loaded by `module::load`.
+    fn import_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Import));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Bruk);
+        match self.token.token_type() {
+            crate::lexer::TokenType::StringLiteral(path) => node.data = path.clone(),
+            _ => panic!(
+                "Expected a file name after `bruk` but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        self.advance();
+        node
+    }
+
+    fn assert_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Assert));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Påstå);
+        node.children.push(self.value());
+        node
+    }
+
+    fn while_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::WhileStatement));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Så);
+        self.expect(crate::lexer::TokenType::Lenge);
+        node.children.push(self.value());
+        self.expect(crate::lexer::TokenType::Gjør);
+        self.expect(crate::lexer::TokenType::Følgende);
+        self.expect(crate::lexer::TokenType::Colon);
+        node.children.push(self.statement());
+        self.expect(crate::lexer::TokenType::Dot);
+        node
+    }
+
+    // `velg dag: tilfelle 1 gjør følgende: …, tilfelle 6 og 7 gjør følgende:
+    // …, ellers gjør følgende: … .` The cases are separated by commas and the
+    // whole choice ends with one dot. A case can have several values, written
+    // like a list, and `ellers` comes last.
+    fn switch_statement(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::SwitchStatement));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Velg);
+        node.children.push(self.value());
+        self.expect(crate::lexer::TokenType::Colon);
+        loop {
+            let mut case = Box::new(Node::new(NodeType::Case));
+            case.token = Some(self.token.clone());
+            match self.token.token_type() {
+                crate::lexer::TokenType::Tilfelle => {
+                    self.advance();
+                    case.children.push(self.expression());
+                    loop {
+                        match self.token.token_type() {
+                            crate::lexer::TokenType::Comma => {
+                                self.advance();
+                                case.children.push(self.expression());
+                            }
+                            crate::lexer::TokenType::Og => {
+                                self.advance();
+                                case.children.push(self.expression());
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                }
+                crate::lexer::TokenType::Ellers => self.advance(),
+                _ => panic!(
+                    "Expected `tilfelle` or `ellers` but found {:?} on line {}",
+                    self.token.token_type(),
+                    self.token.line()
+                ),
+            }
+            self.expect(crate::lexer::TokenType::Gjør);
+            self.expect(crate::lexer::TokenType::Følgende);
+            self.expect(crate::lexer::TokenType::Colon);
+            case.children.push(self.statement());
+            let is_default = case.children.len() == 1;
+            node.children.push(case);
+            if is_default || *self.token.token_type() != crate::lexer::TokenType::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(crate::lexer::TokenType::Dot);
+        node
+    }
+
+    // `avbryt` leaves the innermost loop and `hopp videre` goes on with its
+    // next round.
+    fn loop_control(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Break));
+        node.token = Some(self.token.clone());
+        if *self.token.token_type() == crate::lexer::TokenType::Hopp {
+            self.advance();
+            node.node_type = NodeType::Continue;
+            self.expect(crate::lexer::TokenType::Videre);
+        } else {
+            self.expect(crate::lexer::TokenType::Avbryt);
+        }
+        node
+    }
+
+    // An expression, or a comparison of two expressions when followed by `er`.
+    fn value(&mut self) -> Box<Node> {
+        let expression = self.expression();
+        if *self.token.token_type() == crate::lexer::TokenType::Er {
+            return self.condition(expression);
+        }
+        expression
+    }
+
+    fn condition(&mut self, left: Box<Node>) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::Condition));
-        node.children.push(self.expression());
+        node.children.push(left);
+        node.token = Some(self.token.clone());
         self.expect(crate::lexer::TokenType::Er);
         match self.token.token_type() {
             crate::lexer::TokenType::Lik => {
@@ -188,79 +586,538 @@ impl Parser {
         node
     }
 
+    // Operators of the same precedence are applied left to right, so the tree
+    // leans left: `8 minus 2 minus 1` is `(8 minus 2) minus 1`.
     fn expression(&mut self) -> Box<Node> {
-        let mut node = Box::new(Node::new(NodeType::Expression));
-        node.children.push(self.term());
-        if *self.token.token_type() == crate::lexer::TokenType::Pluss {
+        let mut node = self.term();
+        loop {
+            let operator = match self.token.token_type() {
+                crate::lexer::TokenType::Pluss => "+",
+                crate::lexer::TokenType::Minus => "-",
+                _ => break,
+            };
+            let mut parent = Box::new(Node::new(NodeType::Expression));
+            parent.token = Some(self.token.clone());
             self.advance();
-            node.data = "+".to_string();
-            node.children.push(self.expression());
-        } else if *self.token.token_type() == crate::lexer::TokenType::Minus {
-            self.advance();
-            node.data = "-".to_string();
-            node.children.push(self.expression());
+            parent.data = operator.to_string();
+            parent.children.push(node);
+            parent.children.push(self.term());
+            node = parent;
         }
         node
     }
 
     fn term(&mut self) -> Box<Node> {
-        let mut node = Box::new(Node::new(NodeType::Term));
-        node.children.push(self.factor());
-        if *self.token.token_type() == crate::lexer::TokenType::Ganger {
+        let mut node = self.power();
+        loop {
+            let operator = match self.token.token_type() {
+                crate::lexer::TokenType::Ganger => "*",
+                crate::lexer::TokenType::Delt => "/",
+                _ => break,
+            };
+            let mut parent = Box::new(Node::new(NodeType::Term));
+            parent.token = Some(self.token.clone());
             self.advance();
-            node.data = "*".to_string();
-            node.children.push(self.term());
-        } else if *self.token.token_type() == crate::lexer::TokenType::Delt {
-            self.advance();
-            self.expect(crate::lexer::TokenType::På);
-            node.data = "/".to_string();
-            node.children.push(self.term());
+            if operator == "/" {
+                self.expect(crate::lexer::TokenType::På);
+            }
+            parent.data = operator.to_string();
+            parent.children.push(node);
+            parent.children.push(self.power());
+            node = parent;
         }
         node
     }
 
+    // Powers bind tighter than products and are taken from the right, as in
+    // mathematics: `2 opphøyd i 3 opphøyd i 2` is `2 opphøyd i 9`.
+    fn power(&mut self) -> Box<Node> {
+        let factor = self.factor();
+        if *self.token.token_type() != crate::lexer::TokenType::Opphøyd {
+            return factor;
+        }
+        let mut node = Box::new(Node::new(NodeType::Term));
+        node.token = Some(self.token.clone());
+        self.advance();
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) if name == "i" => self.advance(),
+            _ => panic!(
+                "Expected `i` after `opphøyd` but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        node.data = "^".to_string();
+        node.children.push(factor);
+        node.children.push(self.power());
+        node
+    }
+
+    // `rest av a delt på b` and `kvotienten av a delt på b`, the remainder and
+    // the whole part of a division.
+    fn division_part(&mut self, operator: &str) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Term));
+        node.token = Some(self.token.clone());
+        self.advance();
+        self.expect(crate::lexer::TokenType::Av);
+        node.data = operator.to_string();
+        node.children.push(self.power());
+        self.expect(crate::lexer::TokenType::Delt);
+        self.expect(crate::lexer::TokenType::På);
+        node.children.push(self.power());
+        node
+    }
+
     fn factor(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::Factor));
         match self.token.token_type() {
-            crate::lexer::TokenType::IntLiteral(_) => {
+            crate::lexer::TokenType::IntLiteral(_) | crate::lexer::TokenType::BigIntLiteral(_) => {
                 node.children.push(self.int_literal());
             }
             crate::lexer::TokenType::FloatLiteral(_) => {
                 node.children.push(self.float_literal());
             }
+            crate::lexer::TokenType::BoolLiteral(_) => {
+                node.children.push(self.bool_literal());
+            }
             crate::lexer::TokenType::StringLiteral(_) => {
                 node.children.push(self.string_literal());
             }
             crate::lexer::TokenType::Identifier(_) => {
-                node.children.push(self.identifier());
+                let identifier = self.identifier();
+                if *self.token.token_type() == crate::lexer::TokenType::Til && !self.in_append {
+                    node.children.push(self.field_access(identifier));
+                } else {
+                    node.children.push(identifier);
+                }
             }
             crate::lexer::TokenType::Utfør => {
                 node.children.push(self.function_call());
             }
+            crate::lexer::TokenType::Liste | crate::lexer::TokenType::Tom => {
+                node.children.push(self.list_literal());
+            }
+            crate::lexer::TokenType::Element => {
+                node.children.push(self.element());
+            }
+            crate::lexer::TokenType::Lengden => {
+                node.children.push(self.list_length());
+            }
+            crate::lexer::TokenType::Svaret => {
+                node.children.push(self.read());
+            }
+            crate::lexer::TokenType::Les => {
+                node.children.push(self.file_read());
+            }
+            crate::lexer::TokenType::Oppslag | crate::lexer::TokenType::Tomt => {
+                node.children.push(self.map_literal());
+            }
+            crate::lexer::TokenType::Finnes => {
+                node.children.push(self.contains());
+            }
+            crate::lexer::TokenType::Ny => {
+                node.children.push(self.struct_literal());
+            }
+            crate::lexer::TokenType::Mekanisme => {
+                node.children.push(self.anonymous_function());
+            }
+            crate::lexer::TokenType::Rest => {
+                node.children.push(self.division_part("%"));
+            }
+            crate::lexer::TokenType::Kvotienten => {
+                node.children.push(self.division_part("//"));
+            }
+            crate::lexer::TokenType::Minus => {
+                let mut negation = Box::new(Node::new(NodeType::Negation));
+                negation.token = Some(self.token.clone());
+                self.advance();
+                negation.children.push(self.factor());
+                node.children.push(negation);
+            }
             _ => panic!("Expected factor but found {:?}", self.token.token_type()),
         }
         node
     }
 
+    fn list_literal(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ListLiteral));
+        node.token = Some(self.token.clone());
+        if *self.token.token_type() == crate::lexer::TokenType::Tom {
+            self.advance();
+            self.expect(crate::lexer::TokenType::Liste);
+            return node;
+        }
+        self.expect(crate::lexer::TokenType::Liste);
+        self.expect(crate::lexer::TokenType::Med);
+        node.children.push(self.expression());
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Comma => {
+                    self.advance();
+                    node.children.push(self.expression());
+                }
+                crate::lexer::TokenType::Og => {
+                    self.advance();
+                    node.children.push(self.expression());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    // `i` is not a keyword, as it is a common name for a counter.
+    fn element(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Element));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Element);
+        node.children.push(self.expression());
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) if name == "i" => self.advance(),
+            _ => panic!(
+                "Expected `i` after the index but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        node.children.push(self.factor());
+        node
+    }
+
+    fn list_length(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ListLength));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Lengden);
+        self.expect(crate::lexer::TokenType::Av);
+        node.children.push(self.factor());
+        node
+    }
+
+    // `svaret på "Hva heter du?"` asks the question and gives what was typed.
+    fn read(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Read));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Svaret);
+        self.expect(crate::lexer::TokenType::På);
+        node.children.push(self.factor());
+        node
+    }
+
+    // `les filen "data.txt"` gives the whole file as a string and `les
+    // linjene i filen "data.txt"` gives a list of its lines.
+    fn file_read(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::FileRead));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Les);
+        if *self.token.token_type() == crate::lexer::TokenType::Linjene {
+            self.advance();
+            match self.token.token_type() {
+                crate::lexer::TokenType::Identifier(name) if name == "i" => self.advance(),
+                _ => panic!(
+                    "Expected `i` after `linjene` but found {:?} on line {}",
+                    self.token.token_type(),
+                    self.token.line()
+                ),
+            }
+            node.data = "linjene".to_string();
+        }
+        self.expect(crate::lexer::TokenType::Filen);
+        node.children.push(self.factor());
+        node
+    }
+
+    fn map_literal(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::MapLiteral));
+        node.token = Some(self.token.clone());
+        if *self.token.token_type() == crate::lexer::TokenType::Tomt {
+            self.advance();
+            self.expect(crate::lexer::TokenType::Oppslag);
+            return node;
+        }
+        self.expect(crate::lexer::TokenType::Oppslag);
+        self.expect(crate::lexer::TokenType::Med);
+        node.children.push(self.map_entry());
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Comma => {
+                    self.advance();
+                    node.children.push(self.map_entry());
+                }
+                crate::lexer::TokenType::Og => {
+                    self.advance();
+                    node.children.push(self.map_entry());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    fn map_entry(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::MapEntry));
+        node.children.push(self.expression());
+        self.expect(crate::lexer::TokenType::Som);
+        node.children.push(self.expression());
+        node
+    }
+
+    fn contains(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Contains));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Finnes);
+        node.children.push(self.expression());
+        match self.token.token_type() {
+            crate::lexer::TokenType::Identifier(name) if name == "i" => self.advance(),
+            _ => panic!(
+                "Expected `i` after the value but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            ),
+        }
+        node.children.push(self.factor());
+        node
+    }
+
+    fn struct_definition(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::StructDefinition));
+        self.expect(crate::lexer::TokenType::Struktur);
+        node.children.push(self.identifier());
+        self.expect(crate::lexer::TokenType::Har);
+        node.children.push(self.field());
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Comma => {
+                    self.advance();
+                    node.children.push(self.field());
+                }
+                crate::lexer::TokenType::Og => {
+                    self.advance();
+                    node.children.push(self.field());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    // `et navn` is a field named `navn`, while `et tall alder` is a field
+    // named `alder` that holds a number.
+    fn field(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Field));
+        let has_article = matches!(
+            self.token.token_type(),
+            crate::lexer::TokenType::Et | crate::lexer::TokenType::En
+        );
+        if has_article {
+            self.advance();
+        }
+        let first = self.identifier();
+        if has_article
+            && matches!(
+                self.token.token_type(),
+                crate::lexer::TokenType::Identifier(_)
+            )
+        {
+            let mut type_annotation = Box::new(Node::new(NodeType::TypeAnnotation));
+            if let Some(crate::lexer::TokenType::Identifier(name)) =
+                first.token.as_ref().map(|token| token.token_type())
+            {
+                type_annotation.data = name.clone();
+            }
+            type_annotation.token = first.token;
+            node.children.push(self.identifier());
+            node.children.push(type_annotation);
+        } else {
+            node.children.push(first);
+        }
+        node
+    }
+
+    fn struct_literal(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::StructLiteral));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Ny);
+        node.children.push(self.identifier());
+        if *self.token.token_type() != crate::lexer::TokenType::Med {
+            return node;
+        }
+        self.advance();
+        node.children.push(self.field_value());
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Comma => {
+                    self.advance();
+                    node.children.push(self.field_value());
+                }
+                crate::lexer::TokenType::Og => {
+                    self.advance();
+                    node.children.push(self.field_value());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    fn field_value(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::FieldValue));
+        node.children.push(self.identifier());
+        node.children.push(self.value());
+        node
+    }
+
+    fn field_access(&mut self, field: Box<Node>) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::FieldAccess));
+        node.children.push(field);
+        self.expect(crate::lexer::TokenType::Til);
+        node.children.push(self.factor());
+        node
+    }
+
     fn function_definition(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::FunctionDefinition));
         self.expect(crate::lexer::TokenType::Mekanisme);
         node.children.push(self.identifier());
-        // self.expect(crate::lexer::TokenType::Tar);
-        // self.expect(crate::lexer::TokenType::Inn);
-        // node.children.push(self.identifier());
-        self.expect(crate::lexer::TokenType::Gjør);
-        self.expect(crate::lexer::TokenType::Følgende);
+        node.children.push(self.parameter_list());
+        if *self.token.token_type() == crate::lexer::TokenType::Gjør {
+            self.advance();
+            self.expect(crate::lexer::TokenType::Følgende);
+        }
         self.expect(crate::lexer::TokenType::Colon);
         node.children.push(self.statement());
         self.expect(crate::lexer::TokenType::Dot);
         node
     }
 
+    // `mekanisme som tar x og gir x ganger 2`, a mechanism without a name that
+    // gives the value after `gir`. The `og` before `gir` is taken here, so the
+    // last parameter is not written with `og` as in `tar a og b`.
+    fn anonymous_function(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::AnonymousFunction));
+        node.token = Some(self.token.clone());
+        self.expect(crate::lexer::TokenType::Mekanisme);
+        self.expect(crate::lexer::TokenType::Som);
+        let mut parameters = Box::new(Node::new(NodeType::ParameterList));
+        if *self.token.token_type() == crate::lexer::TokenType::Tar {
+            self.advance();
+            parameters.children.push(self.parameter());
+            loop {
+                match self.token.token_type() {
+                    crate::lexer::TokenType::Comma => {
+                        self.advance();
+                        parameters.children.push(self.parameter());
+                    }
+                    crate::lexer::TokenType::Og => {
+                        self.advance();
+                        if self.is_gir() {
+                            break;
+                        }
+                        parameters.children.push(self.parameter());
+                    }
+                    _ => break,
+                }
+            }
+        }
+        node.children.push(parameters);
+        if !self.is_gir() {
+            panic!(
+                "Expected `gir` but found {:?} on line {}",
+                self.token.token_type(),
+                self.token.line()
+            );
+        }
+        // As if written with `gi`, since `gir` is not a keyword.
+        let mut body = Box::new(Node::new(NodeType::ReturnStatement));
+        body.token = Some(crate::lexer::Token::new(
+            crate::lexer::TokenType::Gi,
+            self.token.line(),
+            self.token.column(),
+        ));
+        self.advance();
+        body.children.push(self.value());
+        node.children.push(body);
+        node
+    }
+
+    fn is_gir(&self) -> bool {
+        matches!(self.token.token_type(), crate::lexer::TokenType::Identifier(name) if name == "gir")
+    }
+
+    fn parameter_list(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::ParameterList));
+        if *self.token.token_type() != crate::lexer::TokenType::Tar {
+            return node;
+        }
+        self.advance();
+        node.children.push(self.parameter());
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Comma => {
+                    self.advance();
+                    node.children.push(self.parameter());
+                }
+                crate::lexer::TokenType::Og => {
+                    self.advance();
+                    node.children.push(self.parameter());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    fn parameter(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Parameter));
+        let type_annotation = match self.token.token_type() {
+            crate::lexer::TokenType::Et | crate::lexer::TokenType::En => {
+                Some(self.type_annotation())
+            }
+            _ => None,
+        };
+        node.children.push(self.identifier());
+        if let Some(type_annotation) = type_annotation {
+            node.children.push(type_annotation);
+        }
+        node
+    }
+
     fn function_call(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::FunctionCall));
+        node.token = Some(self.token.clone());
         self.expect(crate::lexer::TokenType::Utfør);
-        node.children.push(self.identifier());
+        let name = self.mechanism_name();
+        if *self.token.token_type() == crate::lexer::TokenType::Fra {
+            self.advance();
+            let mut qualified = Box::new(Node::new(NodeType::QualifiedName));
+            qualified.children.push(name);
+            qualified.children.push(self.identifier());
+            node.children.push(qualified);
+        } else {
+            node.children.push(name);
+        }
+        if *self.token.token_type() == crate::lexer::TokenType::Med {
+            self.advance();
+            node.children.push(self.value());
+            loop {
+                match self.token.token_type() {
+                    crate::lexer::TokenType::Comma => {
+                        self.advance();
+                        node.children.push(self.value());
+                    }
+                    crate::lexer::TokenType::Og => {
+                        self.advance();
+                        node.children.push(self.value());
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
         self.expect(crate::lexer::TokenType::Exclamation);
         node
     }
@@ -279,6 +1136,13 @@ impl Parser {
         node
     }
 
+    fn bool_literal(&mut self) -> Box<Node> {
+        let mut node = Box::new(Node::new(NodeType::Expression));
+        node.token = Some(self.token.clone());
+        self.advance();
+        node
+    }
+
     fn float_literal(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::Expression));
         node.token = Some(self.token.clone());
@@ -286,10 +1150,50 @@ impl Parser {
         node
     }
 
+    // The name of a mechanism in `utfør`. Built-in mechanisms may have names of
+    // several words, such as `store bokstaver` and `som tall`.
+    fn mechanism_name(&mut self) -> Box<Node> {
+        let token = self.token.clone();
+        let mut words = Vec::new();
+        loop {
+            match self.token.token_type() {
+                crate::lexer::TokenType::Identifier(word) => words.push(word.clone()),
+                crate::lexer::TokenType::Som if words.is_empty() => words.push("som".to_string()),
+                _ => break,
+            }
+            self.advance();
+        }
+        if words.is_empty() {
+            return self.identifier();
+        }
+        let mut node = Box::new(Node::new(NodeType::Expression));
+        node.token = Some(crate::lexer::Token::new(
+            crate::lexer::TokenType::Identifier(words.join(" ")),
+            token.line(),
+            token.column(),
+        ));
+        node
+    }
+
     fn identifier(&mut self) -> Box<Node> {
         let mut node = Box::new(Node::new(NodeType::Expression));
         match self.token.token_type() {
             crate::lexer::TokenType::Identifier(_) => {}
+            // Number words are lexed as numbers, so `la ti være 5` gets here
+            // with the number 10. Parsing goes on with the word as a name.
+            crate::lexer::TokenType::IntLiteral(_) | crate::lexer::TokenType::BigIntLiteral(_) => {
+                let text = self.lexer.last_text();
+                self.diagnostics.push(Diagnostic::at_token(
+                    format!("`{}` er et tall og kan ikke brukes som navn", text),
+                    &self.token,
+                    text.chars().count(),
+                ));
+                self.token = Token::new(
+                    crate::lexer::TokenType::Identifier(text),
+                    self.token.line(),
+                    self.token.column(),
+                );
+            }
             _ => {
                 error!(
                     "Expected identifier but found {:?} at line {}",
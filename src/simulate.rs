@@ -1,223 +1,1004 @@
-use std::{collections::HashMap, panic::PanicInfo};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt, rc::Rc};
 
 use tracing::info;
 
 use crate::{
+    bigint::BigInt,
+    builtins,
     cfg::{BasicBlock, Instruction, Opcode, Operand, CFG},
-    symbol::{StringList, SymbolTable},
+    console::Console,
+    diagnostic::{Module, Span},
+    fraction::Fraction,
+    symbol::{StringList, SymbolRef, SymbolTable},
+    value::{Mechanism, Record, Value},
 };
 
+// An error in the running program, such as dividing by zero, which stops the
+// simulation. The span is that of the instruction that failed, when known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Option<Span>,
+    // The file the span is in, when it is not the program itself.
+    pub module: Option<Module>,
+}
+
+impl RuntimeError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            span: None,
+            module: None,
+        }
+    }
+
+    fn overflow() -> Self {
+        Self::new("tallet ble for stort".to_string())
+    }
+
+    // Formats the error together with the offending source line, e.g.
+    //
+    // kjøretidsfeil: deling på null på linje 3
+    //   |
+    // 3 | si 1 delt på 0.
+    //   |      ^^^^^^^
+    pub fn render(&self, source: &str) -> String {
+        let mut output = format!("{}\n", self);
+        let source = self
+            .module
+            .as_ref()
+            .map_or(source, |module| module.source.as_str());
+        if let Some(span) = &self.span {
+            output += &span.excerpt(source);
+        }
+        output
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "kjøretidsfeil: {}", self.message)?;
+        if let Some(span) = &self.span {
+            write!(f, " på linje {}", span.line)?;
+        }
+        if let Some(module) = &self.module {
+            write!(f, " i {}", module.path)?;
+        }
+        Ok(())
+    }
+}
+
+// How `delt på` divides whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Division {
+    // As whole numbers, rounding towards zero, so `1 delt på 3 ganger 3` is 0.
+    #[default]
+    Whole,
+    // Exactly, as fractions printed like `1/3`.
+    Exact,
+    // Exactly, but with fractions printed as decimal numbers.
+    ExactShownAsDecimal,
+}
+
+// Settings for a simulation given on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub division: Division,
+    // Turns `les filen` and `skriv til filen` into errors, for running
+    // programs that should not touch any files.
+    pub sandbox: bool,
+    // Leaves out every `påstå`, for release runs.
+    pub skip_assertions: bool,
+}
+
 #[derive(Debug, PartialEq)]
 enum BranchDirection {
     Left,
     Right,
+    // The successor with this index, for `Switch`.
+    Case(usize),
+    Call(usize),
+    // Into the body of a mechanism value, with the variables it captured.
+    CallMechanism(Rc<Mechanism>),
+    Return(Option<Value>),
+    Try,
 }
 
-fn simulate_instruction(
-    instruction: &Instruction,
+// Every mechanism call gets its own frame, so recursive calls neither share
+// local variables nor clobber the temporaries of the caller.
+struct Frame {
+    variables: HashMap<SymbolRef, Value>,
+    temporaries: HashMap<usize, Value>,
+    return_block: usize,
+}
+
+impl Frame {
+    fn new(return_block: usize) -> Self {
+        Self {
+            variables: HashMap::new(),
+            temporaries: HashMap::new(),
+            return_block,
+        }
+    }
+}
+
+// Where to go when something fails inside a `prøv`, and how much of the
+// frames and the argument stack belonged to the program before it started.
+struct Handler {
+    block: usize,
+    frames: usize,
+    arguments: usize,
+}
+
+struct State<'a> {
+    globals: HashMap<SymbolRef, Value>,
+    frames: Vec<Frame>,
+    arguments: Vec<Value>,
+    handlers: Vec<Handler>,
+    // The error being handled, until the handler takes its message.
+    error: Option<RuntimeError>,
+    options: Options,
+    console: &'a mut dyn Console,
+}
+
+impl<'a> State<'a> {
+    fn new(options: Options, console: &'a mut dyn Console) -> Self {
+        Self {
+            globals: HashMap::new(),
+            frames: vec![Frame::new(0)],
+            arguments: Vec::new(),
+            handlers: Vec::new(),
+            error: None,
+            options,
+            console,
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    // A global is read from the frame instead when the frame has a copy of
+    // it, which is how a mechanism made with `mekanisme som` sees the globals
+    // it captured.
+    fn lookup_variable(&self, symbol_table: &SymbolTable, symbol_ref: SymbolRef) -> Option<&Value> {
+        let frame = &self.frames.last().unwrap().variables;
+        match frame.get(&symbol_ref) {
+            None if symbol_table.is_global(symbol_ref) => self.globals.get(&symbol_ref),
+            value => value,
+        }
+    }
+
+    // A mechanism called through a variable, or a variable given its value
+    // on only some paths, can still be read before it has a value. The span is
+    // that of the read, which starts at the name.
+    fn variable(
+        &self,
+        symbol_table: &SymbolTable,
+        symbol_ref: SymbolRef,
+        span: Option<Span>,
+    ) -> Result<Value, RuntimeError> {
+        self.lookup_variable(symbol_table, symbol_ref)
+            .cloned()
+            .ok_or_else(|| {
+                let name = &symbol_table.get(symbol_ref).name;
+                RuntimeError {
+                    span: span.map(|span| Span {
+                        length: name.chars().count(),
+                        ..span
+                    }),
+                    ..RuntimeError::new(format!("`{}` ble lest før den fikk en verdi", name))
+                }
+            })
+    }
+
+    fn set_variable(&mut self, symbol_table: &SymbolTable, symbol_ref: SymbolRef, value: Value) {
+        if symbol_table.is_global(symbol_ref) {
+            self.globals.insert(symbol_ref, value);
+        } else {
+            self.frame().variables.insert(symbol_ref, value);
+        }
+    }
+
+    fn temporary(&self, temporary_id: &usize) -> Value {
+        self.frames
+            .last()
+            .unwrap()
+            .temporaries
+            .get(temporary_id)
+            .unwrap()
+            .clone()
+    }
+
+    fn set_temporary(&mut self, temporary_id: usize, value: Value) {
+        self.frame().temporaries.insert(temporary_id, value);
+    }
+}
+
+fn operand_value(
+    operand: &Operand,
     symbol_table: &SymbolTable,
     string_list: &StringList,
-    state: &mut HashMap<String, i64>,
-    temporaries: &mut HashMap<usize, i64>,
-) -> BranchDirection {
-    match instruction.opcode {
-        Opcode::Print => match &instruction.operands[0] {
-            Operand::Immediate(value) => {
-                println!("{}", value);
-            }
-            Operand::Temporary(temporary_id) => {
-                let value = temporaries.get(temporary_id).unwrap();
-                println!("{}", value);
-            }
-            Operand::Variable(symbol_ref) => {
-                let symbol = symbol_table.get(symbol_ref.clone());
-                let value = state.get(&symbol.name).unwrap();
-                println!("{}", value);
+    state: &State,
+    span: Option<Span>,
+) -> Result<Value, RuntimeError> {
+    Ok(match operand {
+        Operand::Immediate(value) => Value::Int(*value),
+        Operand::BigInt(value) => Value::BigInt(value.clone()),
+        Operand::Float(value) => Value::Float(*value),
+        Operand::Bool(value) => Value::Bool(*value),
+        Operand::String(string_id) => Value::String(string_list.get(*string_id).to_string()),
+        Operand::Temporary(temporary_id) => state.temporary(temporary_id),
+        Operand::Variable(symbol_ref) => state.variable(symbol_table, *symbol_ref, span)?,
+        Operand::Label(_) | Operand::Struct(_) | Operand::Field(_) => unreachable!(),
+    })
+}
+
+// Whole number division rounds down, towards negative infinity, and the
+// remainder takes the sign of the divisor, so that `a` is always
+// `kvotienten av a delt på b` times `b` plus `rest av a delt på b`:
+// `kvotienten av -7 delt på 2` is -4 and `rest av -7 delt på 2` is 1.
+fn floor_div(left: i64, right: i64) -> Option<i64> {
+    let quotient = left.checked_div(right)?;
+    if left % right != 0 && (left < 0) != (right < 0) {
+        quotient.checked_sub(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+fn floor_mod(left: i64, right: i64) -> Option<i64> {
+    let remainder = left.checked_rem(right)?;
+    if remainder != 0 && (remainder < 0) != (right < 0) {
+        Some(remainder + right)
+    } else {
+        Some(remainder)
+    }
+}
+
+// Whole numbers never wrap around. A result too large for an i64 is carried
+// on as a big integer, and dividing anything by zero is an error. `delt på` on
+// two whole numbers rounds towards zero, unless dividing exactly, which gives
+// a fraction that stays exact until it meets a decimal number.
+fn arithmetic(
+    opcode: Opcode,
+    left: Value,
+    right: Value,
+    division: Division,
+) -> Result<Value, RuntimeError> {
+    if matches!(opcode, Opcode::Div | Opcode::IntDiv | Opcode::Mod) && right.as_float() == Some(0.0)
+    {
+        return Err(RuntimeError::new("deling på null".to_string()));
+    }
+    let exact = (opcode == Opcode::Div && division != Division::Whole)
+        || matches!(left, Value::Fraction(_))
+        || matches!(right, Value::Fraction(_));
+    if exact {
+        if let (Some(left), Some(right)) = (left.as_fraction(), right.as_fraction()) {
+            return fraction_arithmetic(opcode, &left, &right);
+        }
+    }
+    if let (Some(left), Some(right)) = (left.as_big_int(), right.as_big_int()) {
+        return integer_arithmetic(opcode, &left, &right);
+    }
+    let value = match (opcode, left, right) {
+        (Opcode::Add, Value::String(left), Value::String(right)) => Value::String(left + &right),
+        (opcode, left, right) => {
+            let (Some(left), Some(right)) = (left.as_float(), right.as_float()) else {
+                panic!("Invalid operands for {:?}: {} and {}", opcode, left, right);
+            };
+            match opcode {
+                Opcode::Add => Value::Float(left + right),
+                Opcode::Sub => Value::Float(left - right),
+                Opcode::Mul => Value::Float(left * right),
+                Opcode::Div => Value::Float(left / right),
+                Opcode::IntDiv => Value::Float((left / right).floor()),
+                Opcode::Mod => Value::Float(left - right * (left / right).floor()),
+                Opcode::Pow => Value::Float(left.powf(right)),
+                _ => unreachable!(),
             }
-            Operand::String(string_id) => {
-                let string = string_list.get(string_id.clone());
-                println!("{}", string);
+        }
+    };
+    Ok(value)
+}
+
+// A fraction raised to a fraction is rarely a fraction, so that gives a
+// decimal number, as does raising zero to a negative power.
+fn fraction_arithmetic(
+    opcode: Opcode,
+    left: &Fraction,
+    right: &Fraction,
+) -> Result<Value, RuntimeError> {
+    let value = match opcode {
+        Opcode::Add => left.add(right),
+        Opcode::Sub => left.sub(right),
+        Opcode::Mul => left.mul(right),
+        Opcode::Div => left.div(right),
+        Opcode::IntDiv => Fraction::from_big_int(left.div(right).floor()),
+        Opcode::Mod => left.sub(&right.mul(&Fraction::from_big_int(left.div(right).floor()))),
+        Opcode::Pow => {
+            let exponent = right
+                .as_big_int()
+                .and_then(|exponent| exponent.to_i64())
+                .filter(|exponent| *exponent >= 0 || !left.is_zero());
+            match exponent {
+                Some(exponent) => {
+                    let power = u32::try_from(exponent.unsigned_abs())
+                        .map_err(|_| RuntimeError::overflow())?;
+                    if exponent < 0 {
+                        left.pow(power).reciprocal()
+                    } else {
+                        left.pow(power)
+                    }
+                }
+                None => return Ok(Value::Float(left.to_f64().powf(right.to_f64()))),
             }
-            _ => {
-                unreachable!();
+        }
+        _ => unreachable!(),
+    };
+    Ok(Value::from_fraction(value))
+}
+
+// The cheap i64 operations are tried first, and only when they overflow is
+// the operation done again on big integers.
+fn integer_arithmetic(
+    opcode: Opcode,
+    left: &BigInt,
+    right: &BigInt,
+) -> Result<Value, RuntimeError> {
+    if let (Some(left), Some(right)) = (left.to_i64(), right.to_i64()) {
+        let value = match opcode {
+            Opcode::Add => left.checked_add(right),
+            Opcode::Sub => left.checked_sub(right),
+            Opcode::Mul => left.checked_mul(right),
+            Opcode::Div => left.checked_div(right),
+            Opcode::IntDiv => floor_div(left, right),
+            Opcode::Mod => floor_mod(left, right),
+            Opcode::Pow => u32::try_from(right)
+                .ok()
+                .and_then(|right| left.checked_pow(right)),
+            _ => unreachable!(),
+        };
+        if let Some(value) = value {
+            return Ok(Value::Int(value));
+        }
+    }
+    let value = match opcode {
+        Opcode::Add => left.add(right),
+        Opcode::Sub => left.sub(right),
+        Opcode::Mul => left.mul(right),
+        Opcode::Div => left.div(right),
+        Opcode::IntDiv => left.div_mod_floor(right).0,
+        Opcode::Mod => left.div_mod_floor(right).1,
+        Opcode::Pow => {
+            if *right < BigInt::from_i64(0) {
+                return Err(RuntimeError::new(format!(
+                    "kan ikke opphøye heltallet {} i {}, bruk et desimaltall",
+                    left, right
+                )));
             }
-        },
-        Opcode::Set => match &instruction.operands[0] {
-            Operand::Variable(symbol_ref) => match &instruction.operands[1] {
-                Operand::Immediate(value) => {
-                    let symbol = symbol_table.get(symbol_ref.clone());
-                    let value = value.clone();
-                    state.insert(symbol.name.clone(), value);
+            let exponent = right
+                .to_i64()
+                .and_then(|right| u32::try_from(right).ok())
+                .ok_or_else(RuntimeError::overflow)?;
+            left.pow(exponent)
+        }
+        _ => unreachable!(),
+    };
+    Ok(Value::from_big_int(value))
+}
+
+fn simulate_instruction(
+    instruction: &Instruction,
+    symbol_table: &SymbolTable,
+    string_list: &StringList,
+    state: &mut State,
+) -> Result<BranchDirection, RuntimeError> {
+    let value_of = |operand: &Operand, state: &State| {
+        operand_value(operand, symbol_table, string_list, state, instruction.span)
+    };
+    match instruction.opcode {
+        Opcode::Print => {
+            let value = value_of(&instruction.operands[0], state)?;
+            let text = if state.options.division == Division::ExactShownAsDecimal {
+                format!("{}\n", value.with_decimal_fractions())
+            } else {
+                format!("{}\n", value)
+            };
+            state.console.write(&text);
+        }
+        Opcode::Set => {
+            let value = value_of(&instruction.operands[1], state)?;
+            match &instruction.operands[0] {
+                Operand::Variable(symbol_ref) => {
+                    state.set_variable(symbol_table, *symbol_ref, value);
                 }
                 Operand::Temporary(temporary_id) => {
-                    let symbol = symbol_table.get(symbol_ref.clone());
-                    let value = temporaries.get(temporary_id).unwrap();
-                    state.insert(symbol.name.clone(), value.clone());
+                    state.set_temporary(*temporary_id, value);
                 }
-                _ => {}
-            },
-            Operand::Temporary(temporary_id) => match &instruction.operands[1] {
-                Operand::Immediate(value) => {
-                    let value = value.clone();
-                    temporaries.insert(temporary_id.clone(), value);
-                }
-                Operand::Temporary(temporary_id) => {
-                    let value = temporaries.get(temporary_id).unwrap().clone();
-                    temporaries.insert(temporary_id.clone(), value);
+                _ => unreachable!(),
+            }
+        }
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::IntDiv
+        | Opcode::Mod
+        | Opcode::Pow => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
+                _ => unreachable!(),
+            };
+            let left = value_of(&instruction.operands[1], state)?;
+            let right = value_of(&instruction.operands[2], state)?;
+            state.set_temporary(
+                temporary_id,
+                arithmetic(instruction.opcode, left, right, state.options.division)?,
+            );
+        }
+        Opcode::If => {
+            let target = value_of(&instruction.operands[0], state)?;
+            if !target.is_true() {
+                return Ok(BranchDirection::Right);
+            }
+        }
+        // Operands are the value, the lowest case and the number of slots.
+        // Values outside the table take the successor after the last slot.
+        Opcode::Switch => {
+            let value = value_of(&instruction.operands[0], state)?;
+            let (Operand::Immediate(low), Operand::Immediate(slots)) =
+                (&instruction.operands[1], &instruction.operands[2])
+            else {
+                unreachable!()
+            };
+            let slot = match value {
+                Value::Int(value) => value
+                    .checked_sub(*low)
+                    .filter(|slot| (0..*slots).contains(slot)),
+                _ => None,
+            };
+            return Ok(BranchDirection::Case(slot.unwrap_or(*slots) as usize));
+        }
+        Opcode::CmpEq | Opcode::CmpLt | Opcode::CmpLe | Opcode::CmpGt | Opcode::CmpGe => {
+            info!("{:?}", state.frame().temporaries);
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
+                _ => unreachable!(),
+            };
+            let left = value_of(&instruction.operands[1], state)?;
+            let right = value_of(&instruction.operands[2], state)?;
+            let result = match instruction.opcode {
+                Opcode::CmpEq => left.equals(&right),
+                Opcode::CmpLt => left.compare(&right) == Some(Ordering::Less),
+                Opcode::CmpLe => {
+                    matches!(left.compare(&right), Some(Ordering::Less | Ordering::Equal))
                 }
+                Opcode::CmpGt => left.compare(&right) == Some(Ordering::Greater),
+                _ => matches!(
+                    left.compare(&right),
+                    Some(Ordering::Greater | Ordering::Equal)
+                ),
+            };
+            state.set_temporary(temporary_id, Value::Bool(result));
+        }
+        Opcode::Push => {
+            let value = value_of(&instruction.operands[0], state)?;
+            state.arguments.push(value);
+        }
+        Opcode::Pop => {
+            let value = state.arguments.pop().expect("Argument stack is empty");
+            match &instruction.operands[0] {
                 Operand::Variable(symbol_ref) => {
-                    let symbol = symbol_table.get(symbol_ref.clone());
-                    let value = state.get(&symbol.name).unwrap().clone();
-                    temporaries.insert(temporary_id.clone(), value);
+                    state.set_variable(symbol_table, *symbol_ref, value);
+                }
+                _ => unreachable!(),
+            }
+        }
+        Opcode::Call => match &instruction.operands[1] {
+            Operand::Label(entry) => return Ok(BranchDirection::Call(*entry)),
+            Operand::Variable(symbol_ref) => {
+                call_builtin(instruction, symbol_table, *symbol_ref, state)?;
+            }
+            // A mechanism held in a value, with the number of arguments pushed
+            // for it.
+            Operand::Temporary(_) => {
+                let mechanism = match value_of(&instruction.operands[1], state)? {
+                    Value::Mechanism(mechanism) => mechanism,
+                    value => {
+                        return Err(RuntimeError::new(format!(
+                            "kan bare utføre mekanismer, ikke {}",
+                            value
+                        )))
+                    }
+                };
+                let Operand::Immediate(arguments) = instruction.operands[2] else {
+                    unreachable!()
+                };
+                let symbol = symbol_table.get(mechanism.symbol);
+                if symbol.parameters.len() as i64 != arguments {
+                    let count = state.arguments.len() - arguments as usize;
+                    state.arguments.truncate(count);
+                    return Err(RuntimeError::new(format!(
+                        "mekanismen tar {} verdier, men fikk {}",
+                        symbol.parameters.len(),
+                        arguments
+                    )));
+                }
+                if symbol.builtin {
+                    call_builtin(instruction, symbol_table, mechanism.symbol, state)?;
+                } else {
+                    return Ok(BranchDirection::CallMechanism(mechanism));
                 }
-                _ => {}
-            },
-            _ => {}
+            }
+            _ => unreachable!(),
         },
-        Opcode::Add => {
+        // Copies the variables the mechanism captures, leaving out any that has
+        // not been given a value on the way here.
+        Opcode::Closure => {
+            let Operand::Variable(symbol_ref) = instruction.operands[1] else {
+                unreachable!()
+            };
+            let captured = symbol_table
+                .get(symbol_ref)
+                .captures
+                .iter()
+                .filter_map(|captured| {
+                    let value = state.lookup_variable(symbol_table, *captured)?;
+                    Some((*captured, value.clone()))
+                })
+                .collect();
+            let mechanism = Mechanism {
+                symbol: symbol_ref,
+                captured,
+            };
+            if let Operand::Temporary(temporary_id) = &instruction.operands[0] {
+                state.set_temporary(*temporary_id, Value::Mechanism(Rc::new(mechanism)));
+            }
+        }
+        Opcode::Ret => {
+            let value = instruction
+                .operands
+                .first()
+                .map(|operand| value_of(operand, state))
+                .transpose()?;
+            return Ok(BranchDirection::Return(value));
+        }
+        Opcode::ListNew => {
             let temporary_id = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporary_id.clone(),
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let left = match &instruction.operands[1] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+            state.set_temporary(temporary_id, Value::new_list());
+        }
+        Opcode::ListPush => {
+            let list = value_of(&instruction.operands[0], state)?;
+            let value = value_of(&instruction.operands[1], state)?;
+            match list {
+                Value::List(values) => values.borrow_mut().push(value),
+                _ => panic!("Cannot append to {}", list),
+            }
+        }
+        Opcode::ListGet => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let right = match &instruction.operands[2] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
-                _ => unreachable!(),
+            let list = value_of(&instruction.operands[1], state)?;
+            let index = value_of(&instruction.operands[2], state)?;
+            let Value::List(values) = &list else {
+                panic!("Cannot index {} with {}", list, index);
             };
-            temporaries.insert(temporary_id, left + right);
+            // Elements are counted from 1, as in `element 1 i listen`. A big
+            // integer is never a valid position.
+            let position = match index {
+                Value::Int(index) => index,
+                Value::BigInt(_) | Value::Fraction(_) => -1,
+                _ => panic!("Cannot index {} with {}", list, index),
+            };
+            let value = usize::try_from(position)
+                .ok()
+                .and_then(|index| index.checked_sub(1))
+                .and_then(|index| values.borrow().get(index).cloned());
+            match value {
+                Some(value) => state.set_temporary(temporary_id, value),
+                None => {
+                    return Err(RuntimeError::new(format!(
+                        "element {} finnes ikke, listen har {} elementer",
+                        index,
+                        values.borrow().len()
+                    )))
+                }
+            }
         }
-        Opcode::Sub => {
+        Opcode::ListLen => {
             let temporary_id = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporary_id.clone(),
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let left = match &instruction.operands[1] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
-                _ => unreachable!(),
+            let length = match value_of(&instruction.operands[1], state)? {
+                Value::List(values) => values.borrow().len(),
+                Value::Map(map) => map.borrow().len(),
+                Value::String(value) => value.chars().count(),
+                value => panic!("Cannot take the length of {}", value),
             };
-            let right = match &instruction.operands[2] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+            state.set_temporary(temporary_id, Value::Int(length as i64));
+        }
+        Opcode::Read => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            temporaries.insert(temporary_id, left - right);
+            let prompt = value_of(&instruction.operands[1], state)?;
+            state.console.write(&format!("{} ", prompt));
+            let Some(answer) = state.console.read_line() else {
+                return Err(RuntimeError::new(format!(
+                    "fikk ikke noe svar på \"{}\"",
+                    prompt
+                )));
+            };
+            state.set_temporary(temporary_id, Value::String(answer));
         }
-        Opcode::Mul => {
+        // Turns an answer into a whole number if the last operand is true,
+        // otherwise into a decimal number.
+        Opcode::Parse => {
             let temporary_id = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporary_id.clone(),
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let left = match &instruction.operands[1] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
-                _ => unreachable!(),
+            let Value::String(text) = value_of(&instruction.operands[1], state)? else {
+                panic!("Can only parse strings");
             };
-            let right = match &instruction.operands[2] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
-                _ => unreachable!(),
+            let value = match instruction.operands[2] {
+                Operand::Bool(true) => Value::parse_int(text.trim()),
+                _ => text.trim().parse().ok().map(Value::Float),
             };
-            temporaries.insert(temporary_id, left * right);
+            match value {
+                Some(value) => state.set_temporary(temporary_id, value),
+                None => {
+                    return Err(RuntimeError::new(format!(
+                        "svaret \"{}\" er ikke et tall",
+                        text
+                    )))
+                }
+            }
         }
-        Opcode::Div => {
+        Opcode::ReadFile => {
             let temporary_id = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporary_id.clone(),
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let left = match &instruction.operands[1] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
-                _ => unreachable!(),
+            let Value::String(name) = value_of(&instruction.operands[1], state)? else {
+                panic!("File name is not a string");
+            };
+            check_file_access(state, &name)?;
+            let contents = state
+                .console
+                .read_file(&name)
+                .map_err(|error| file_error("lese", &name, error))?;
+            let value = match instruction.operands[2] {
+                Operand::Bool(true) => Value::List(Rc::new(RefCell::new(
+                    contents
+                        .lines()
+                        .map(|line| Value::String(line.to_string()))
+                        .collect(),
+                ))),
+                _ => Value::String(contents),
+            };
+            state.set_temporary(temporary_id, value);
+        }
+        Opcode::WriteFile => {
+            let Value::String(name) = value_of(&instruction.operands[0], state)? else {
+                panic!("File name is not a string");
             };
-            let right = match &instruction.operands[2] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+            check_file_access(state, &name)?;
+            let contents = value_of(&instruction.operands[1], state)?.to_string();
+            state
+                .console
+                .write_file(&name, &contents)
+                .map_err(|error| file_error("skrive til", &name, error))?;
+        }
+        Opcode::Try => return Ok(BranchDirection::Try),
+        Opcode::EndTry => {
+            state.handlers.pop().expect("No handler to remove");
+        }
+        Opcode::Catch => {
+            let error = state
+                .error
+                .take()
+                .expect("Handler reached without an error");
+            if let Some(Operand::Variable(symbol_ref)) = instruction.operands.first() {
+                state.set_variable(symbol_table, *symbol_ref, Value::String(error.message));
+            }
+        }
+        Opcode::Raise => {
+            let message = value_of(&instruction.operands[0], state)?;
+            return Err(RuntimeError::new(message.to_string()));
+        }
+        Opcode::Assertions => {
+            if state.options.skip_assertions {
+                return Ok(BranchDirection::Right);
+            }
+        }
+        Opcode::Trap => {
+            let message = match instruction.operands.as_slice() {
+                [left, right] => format!(
+                    "påstanden holder ikke, venstre side er {} og høyre side er {}",
+                    value_of(left, state)?,
+                    value_of(right, state)?
+                ),
+                _ => "påstanden holder ikke".to_string(),
+            };
+            return Err(RuntimeError::new(message));
+        }
+        Opcode::MapNew => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            temporaries.insert(temporary_id, left / right);
+            state.set_temporary(temporary_id, Value::new_map());
         }
-        Opcode::If => {
-            let target = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+        Opcode::MapInsert => {
+            let map = value_of(&instruction.operands[0], state)?;
+            let key = value_of(&instruction.operands[1], state)?;
+            let value = value_of(&instruction.operands[2], state)?;
+            match map {
+                Value::Map(map) => map.borrow_mut().insert(key, value),
+                _ => panic!("Cannot insert into {}", map),
+            }
+        }
+        Opcode::MapGet => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            if target == 0 {
-                return BranchDirection::Right;
+            let map = value_of(&instruction.operands[1], state)?;
+            let key = value_of(&instruction.operands[2], state)?;
+            let Value::Map(map) = map else {
+                panic!("Cannot look up {} in {}", key, map);
+            };
+            let value = map.borrow().get(&key).cloned();
+            match value {
+                Some(value) => state.set_temporary(temporary_id, value),
+                None => {
+                    return Err(RuntimeError::new(format!(
+                        "nøkkelen `{}` finnes ikke i oppslaget",
+                        key
+                    )))
+                }
             }
         }
-        Opcode::CmpEq => {
-            info!("{:?}", temporaries);
+        Opcode::MapKeys => {
             let temporary_id = match &instruction.operands[0] {
-                Operand::Temporary(temporary_id) => temporary_id.clone(),
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let left = match &instruction.operands[1] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+            let keys = match value_of(&instruction.operands[1], state)? {
+                Value::Map(map) => map.borrow().keys().cloned().collect(),
+                value => panic!("Cannot take the keys of {}", value),
+            };
+            state.set_temporary(temporary_id, Value::List(Rc::new(RefCell::new(keys))));
+        }
+        Opcode::Contains => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            let right = match &instruction.operands[2] {
-                Operand::Immediate(value) => value.clone(),
-                Operand::Temporary(temporary_id) => temporaries.get(temporary_id).unwrap().clone(),
+            let container = value_of(&instruction.operands[1], state)?;
+            let item = value_of(&instruction.operands[2], state)?;
+            let found = match &container {
+                Value::List(values) => values.borrow().iter().any(|value| value.equals(&item)),
+                Value::Map(map) => map.borrow().contains_key(&item),
+                _ => panic!("Cannot look for {} in {}", item, container),
+            };
+            state.set_temporary(temporary_id, Value::Bool(found));
+        }
+        Opcode::StructNew => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
                 _ => unreachable!(),
             };
-            if left == right {
-                temporaries.insert(temporary_id, 1);
-            } else {
-                temporaries.insert(temporary_id, 0);
+            let Operand::Struct(structure) = &instruction.operands[1] else {
+                unreachable!();
+            };
+            let mut given = HashMap::new();
+            for pair in instruction.operands[2..].chunks(2) {
+                let Operand::Field(field) = &pair[0] else {
+                    unreachable!();
+                };
+                given.insert(*field, value_of(&pair[1], state)?);
             }
+            // Fields are kept in the order of the declaration.
+            let structure = symbol_table.get(*structure);
+            let fields = structure
+                .fields
+                .iter()
+                .map(|field| {
+                    let value = given.remove(field).expect("Field without a value");
+                    (symbol_table.get(*field).name.clone(), value)
+                })
+                .collect();
+            let record = Record {
+                name: structure.name.clone(),
+                fields,
+            };
+            state.set_temporary(temporary_id, Value::Struct(Rc::new(RefCell::new(record))));
+        }
+        Opcode::FieldLoad => {
+            let temporary_id = match &instruction.operands[0] {
+                Operand::Temporary(temporary_id) => *temporary_id,
+                _ => unreachable!(),
+            };
+            let record = value_of(&instruction.operands[1], state)?;
+            let Operand::Field(field) = &instruction.operands[2] else {
+                unreachable!();
+            };
+            let Value::Struct(record) = record else {
+                panic!("Cannot read a field of {}", record);
+            };
+            let name = &symbol_table.get(*field).name;
+            let value = record
+                .borrow()
+                .fields
+                .iter()
+                .find(|(field_name, _)| field_name == name)
+                .map(|(_, value)| value.clone())
+                .expect("No field with the given name");
+            state.set_temporary(temporary_id, value);
         }
-        _ => {
-            println!("unimplemented instruction: {:?}", instruction);
+        Opcode::FieldStore => {
+            let record = value_of(&instruction.operands[0], state)?;
+            let Operand::Field(field) = &instruction.operands[1] else {
+                unreachable!();
+            };
+            let value = value_of(&instruction.operands[2], state)?;
+            let Value::Struct(record) = record else {
+                panic!("Cannot set a field of {}", record);
+            };
+            let name = &symbol_table.get(*field).name;
+            let mut record = record.borrow_mut();
+            if let Some((_, field_value)) = record
+                .fields
+                .iter_mut()
+                .find(|(field_name, _)| field_name == name)
+            {
+                *field_value = value;
+            }
         }
     }
 
-    return BranchDirection::Left;
+    Ok(BranchDirection::Left)
+}
+
+fn check_file_access(state: &State, name: &str) -> Result<(), RuntimeError> {
+    if state.options.sandbox {
+        return Err(RuntimeError::new(format!(
+            "kan ikke bruke filen \"{}\", filer er slått av i sandkassen",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn file_error(action: &str, name: &str, error: std::io::Error) -> RuntimeError {
+    let reason = match error.kind() {
+        std::io::ErrorKind::NotFound => "den finnes ikke".to_string(),
+        std::io::ErrorKind::PermissionDenied => "mangler tilgang".to_string(),
+        _ => error.to_string(),
+    };
+    RuntimeError::new(format!(
+        "kunne ikke {} filen \"{}\": {}",
+        action, name, reason
+    ))
+}
+
+fn call_builtin(
+    instruction: &Instruction,
+    symbol_table: &SymbolTable,
+    symbol_ref: SymbolRef,
+    state: &mut State,
+) -> Result<(), RuntimeError> {
+    let name = &symbol_table.get(symbol_ref).name;
+    let builtin =
+        builtins::find(name).unwrap_or_else(|| panic!("Call to {} which has no body", name));
+    let count = state.arguments.len() - builtin.parameters.len();
+    let arguments = state.arguments.split_off(count);
+    let value = (builtin.run)(arguments)?;
+    if let Operand::Temporary(temporary_id) = &instruction.operands[0] {
+        state.set_temporary(*temporary_id, value);
+    }
+    Ok(())
 }
 
 fn simulate_basic_block(
     basic_block: &BasicBlock,
     symbol_table: &SymbolTable,
     string_list: &StringList,
-    state: &mut HashMap<String, i64>,
-    temporaries: &mut HashMap<usize, i64>,
-) -> BranchDirection {
+    state: &mut State,
+) -> Result<BranchDirection, RuntimeError> {
     for instruction in basic_block.get_instructions() {
-        if simulate_instruction(instruction, symbol_table, string_list, state, temporaries)
-            == BranchDirection::Right
-        {
-            return BranchDirection::Right;
+        let direction = simulate_instruction(instruction, symbol_table, string_list, state)
+            .map_err(|error| RuntimeError {
+                span: error.span.or(instruction.span),
+                ..error
+            })?;
+        if direction != BranchDirection::Left {
+            return Ok(direction);
         }
     }
-    return BranchDirection::Left;
+    Ok(BranchDirection::Left)
 }
 
-pub fn simulate_cfg(cfg: &CFG, symbol_table: &SymbolTable, string_list: &StringList) {
-    let mut state: HashMap<String, i64> = HashMap::new();
-    let mut temporaries: HashMap<usize, i64> = HashMap::new();
+pub fn simulate_cfg(
+    cfg: &CFG,
+    symbol_table: &SymbolTable,
+    string_list: &StringList,
+    options: Options,
+    console: &mut dyn Console,
+) -> Result<(), RuntimeError> {
+    let mut state = State::new(options, console);
     let mut id = cfg.entry_block();
     loop {
         let block = cfg.get_block(id);
-        let branch_dir = simulate_basic_block(
-            block,
-            symbol_table,
-            string_list,
-            &mut state,
-            &mut temporaries,
-        );
-        if branch_dir == BranchDirection::Right {
-            id = cfg.get_successors(id)[1];
-        } else {
-            id = cfg.get_successors(id)[0];
+        let branch_dir = match simulate_basic_block(block, symbol_table, string_list, &mut state) {
+            Ok(branch_dir) => branch_dir,
+            // Unwind to the innermost `prøv`, leaving any mechanisms called
+            // since it started.
+            Err(error) => {
+                let Some(handler) = state.handlers.pop() else {
+                    return Err(error);
+                };
+                state.frames.truncate(handler.frames);
+                state.arguments.truncate(handler.arguments);
+                state.error = Some(error);
+                id = handler.block;
+                continue;
+            }
+        };
+        match branch_dir {
+            BranchDirection::Left => id = cfg.get_successors(id)[0],
+            BranchDirection::Right => id = cfg.get_successors(id)[1],
+            BranchDirection::Case(index) => id = cfg.get_successors(id)[index],
+            BranchDirection::Call(entry) => {
+                state.frames.push(Frame::new(id));
+                id = entry;
+            }
+            BranchDirection::CallMechanism(mechanism) => {
+                let mut frame = Frame::new(id);
+                frame.variables = mechanism.captured.clone();
+                state.frames.push(frame);
+                id = cfg.function_entry(mechanism.symbol);
+            }
+            BranchDirection::Return(value) => {
+                let frame = state.frames.pop().unwrap();
+                if let Some(value) = value {
+                    let call = cfg.get_block(frame.return_block);
+                    if let Some(Operand::Temporary(result)) = call
+                        .get_instructions()
+                        .last()
+                        .map(|instruction| &instruction.operands[0])
+                    {
+                        state.set_temporary(*result, value);
+                    }
+                }
+                id = cfg.get_successors(frame.return_block)[0];
+                // A `gi` inside a `prøv` leaves it as well.
+                let depth = state.frames.len();
+                state.handlers.retain(|handler| handler.frames <= depth);
+            }
+            BranchDirection::Try => {
+                let successors = cfg.get_successors(id);
+                state.handlers.push(Handler {
+                    block: successors[1],
+                    frames: state.frames.len(),
+                    arguments: state.arguments.len(),
+                });
+                id = successors[0];
+            }
         }
         if id == cfg.exit_block() {
             break;
         }
     }
+    Ok(())
 }
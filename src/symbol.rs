@@ -1,11 +1,24 @@
-use std::{collections::HashMap, error::Error, fs::File, io::Write};
+use std::{collections::HashMap, error::Error, fmt, fs::File, io::Write};
+
+use crate::utils;
 
 pub type SymbolRef = usize;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SymbolKind {
     Variable,
+    Constant,
     Function,
+    Struct,
+    Field,
+    Module,
+}
+
+impl SymbolKind {
+    // Constants can be read anywhere a variable can.
+    pub fn satisfies(&self, wanted: &SymbolKind) -> bool {
+        self == wanted || (*self == SymbolKind::Constant && *wanted == SymbolKind::Variable)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +29,102 @@ pub enum Type {
     String,
     Bool,
     Void,
+    List(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Struct { name: String, symbol: SymbolRef },
+    // The types of the parameters and of the result, and every mechanism a
+    // value of the type may be, so that a call through it can decide the
+    // types of their parameters.
+    Mechanism(Vec<Type>, Box<Type>, Vec<SymbolRef>),
+}
+
+impl Type {
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    // Only values that can be compared exactly can be keys in an `oppslag`.
+    pub fn is_key(&self) -> bool {
+        matches!(self, Type::Unknown | Type::Int | Type::String | Type::Bool)
+    }
+
+    // The more precise of two types that may describe the same values, such
+    // as `liste med tall` for an empty list that later gets numbers, or None
+    // when they cannot.
+    pub fn merge(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            (Type::Unknown, _) => Some(other.clone()),
+            (_, Type::Unknown) => Some(self.clone()),
+            (Type::List(left), Type::List(right)) => Some(Type::List(Box::new(left.merge(right)?))),
+            (Type::Map(left_key, left_value), Type::Map(right_key, right_value)) => {
+                Some(Type::Map(
+                    Box::new(left_key.merge(right_key)?),
+                    Box::new(left_value.merge(right_value)?),
+                ))
+            }
+            (
+                Type::Mechanism(left, left_result, left_symbols),
+                Type::Mechanism(right, right_result, right_symbols),
+            ) if left.len() == right.len() => {
+                let parameters = left
+                    .iter()
+                    .zip(right)
+                    .map(|(left, right)| left.merge(right))
+                    .collect::<Option<Vec<Type>>>()?;
+                let mut symbols = left_symbols.clone();
+                for symbol in right_symbols {
+                    if !symbols.contains(symbol) {
+                        symbols.push(*symbol);
+                    }
+                }
+                symbols.sort();
+                Some(Type::Mechanism(
+                    parameters,
+                    Box::new(left_result.merge(right_result)?),
+                    symbols,
+                ))
+            }
+            _ if self == other => Some(self.clone()),
+            _ => None,
+        }
+    }
+}
+
+// The names used for types in messages to the programmer.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Unknown => "ukjent",
+            Type::Int => "tall",
+            Type::Float => "desimaltall",
+            Type::String => "streng",
+            Type::Bool => "sannhetsverdi",
+            Type::Void => "ingenting",
+            Type::List(element) if **element == Type::Unknown => "liste",
+            Type::List(element) => return write!(f, "liste med {}", element),
+            Type::Map(key, value) if **key == Type::Unknown && **value == Type::Unknown => {
+                "oppslag"
+            }
+            Type::Map(key, value) => return write!(f, "oppslag fra {} til {}", key, value),
+            Type::Struct { name, .. } => name,
+            Type::Mechanism(parameters, result, _) => {
+                write!(f, "mekanisme")?;
+                if let Some((last, rest)) = parameters.split_last() {
+                    write!(f, " som tar ")?;
+                    for parameter in rest {
+                        write!(f, "{}, ", parameter)?;
+                    }
+                    write!(f, "{}", last)?;
+                }
+                return match **result {
+                    Type::Unknown | Type::Void => Ok(()),
+                    _ if parameters.is_empty() => write!(f, " som gir {}", result),
+                    _ => write!(f, " og gir {}", result),
+                };
+            }
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -23,68 +132,330 @@ pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
     pub type_: Type,
+    pub declared_type: Option<Type>,
+    pub parameters: Vec<SymbolRef>,
+    pub fields: Vec<SymbolRef>,
+    pub scope: ScopeRef,
+    // Whether this is a mechanism that comes with the language, see
+    // `builtins`.
+    pub builtin: bool,
+    // The scope holding the mechanisms of a module.
+    pub members: Option<ScopeRef>,
+    // For a mechanism made with `mekanisme som`, the variables of the
+    // mechanisms around it that it reads.
+    pub captures: Vec<SymbolRef>,
+}
+
+pub type ScopeRef = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeKind {
+    // Holds the mechanisms that come with the language. It lies around the
+    // global scope, so a program may use their names for its own symbols.
+    Builtin,
+    Global,
+    Mechanism,
+    Block,
+    Loop,
+    Struct,
+    Module,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeRef>,
+    pub children: Vec<ScopeRef>,
+    symbols: HashMap<String, SymbolRef>,
 }
 
 pub struct SymbolTable {
     symbols: HashMap<SymbolRef, Box<Symbol>>,
+    scopes: Vec<Scope>,
+    // The scope of every file brought in with `bruk`, by its path, so a file
+    // used from several places is only declared once.
+    modules: HashMap<String, ScopeRef>,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
-        Self {
+        let builtin = Scope {
+            kind: ScopeKind::Builtin,
+            parent: None,
+            children: Vec::new(),
             symbols: HashMap::new(),
-        }
+        };
+        let mut symbol_table = Self {
+            symbols: HashMap::new(),
+            scopes: vec![builtin],
+            modules: HashMap::new(),
+        };
+        symbol_table.add_scope(ScopeKind::Global, symbol_table.builtin_scope());
+        symbol_table
     }
 
-    pub fn add(&mut self, name: &str, kind: SymbolKind) -> SymbolRef {
-        if self.contains_name(name) {
-            for (symbol_ref, symbol) in self.symbols.iter() {
-                if symbol.name == name {
-                    return *symbol_ref;
-                }
-            }
-        }
+    pub fn builtin_scope(&self) -> ScopeRef {
+        0
+    }
+
+    pub fn global_scope(&self) -> ScopeRef {
+        1
+    }
+
+    pub fn add_scope(&mut self, kind: ScopeKind, parent: ScopeRef) -> ScopeRef {
+        let scope_ref = self.scopes.len();
+        self.scopes.push(Scope {
+            kind,
+            parent: Some(parent),
+            children: Vec::new(),
+            symbols: HashMap::new(),
+        });
+        self.scopes[parent].children.push(scope_ref);
+        scope_ref
+    }
+
+    // Declares a new symbol in `scope`, shadowing any symbol of the same name
+    // in the enclosing scopes.
+    pub fn declare(&mut self, scope: ScopeRef, name: &str, kind: SymbolKind) -> SymbolRef {
         let symbol = Symbol {
             name: name.to_string(),
             kind,
             type_: Type::Unknown,
+            declared_type: None,
+            parameters: Vec::new(),
+            fields: Vec::new(),
+            scope,
+            builtin: false,
+            members: None,
+            captures: Vec::new(),
         };
         let symbol_ref = self.symbols.len();
         self.symbols.insert(symbol_ref, Box::new(symbol));
+        self.scopes[scope]
+            .symbols
+            .insert(name.to_string(), symbol_ref);
         symbol_ref
     }
 
-    pub fn get(&self, symbol_ref: SymbolRef) -> &Box<Symbol> {
+    pub fn get(&self, symbol_ref: SymbolRef) -> &Symbol {
         self.symbols.get(&symbol_ref).unwrap()
     }
 
-    pub fn get_symbol_ref(&self, name: &String) -> Option<SymbolRef> {
-        for (symbol_ref, symbol) in self.symbols.iter() {
-            if symbol.name == *name {
-                return Some(*symbol_ref);
+    pub fn set_type(&mut self, symbol_ref: SymbolRef, type_: Type) {
+        self.symbols.get_mut(&symbol_ref).unwrap().type_ = type_;
+    }
+
+    pub fn set_declared_type(&mut self, symbol_ref: SymbolRef, type_: Type) {
+        self.symbols.get_mut(&symbol_ref).unwrap().declared_type = Some(type_);
+    }
+
+    pub fn set_parameters(&mut self, symbol_ref: SymbolRef, parameters: Vec<SymbolRef>) {
+        self.symbols.get_mut(&symbol_ref).unwrap().parameters = parameters;
+    }
+
+    pub fn set_captures(&mut self, symbol_ref: SymbolRef, captures: Vec<SymbolRef>) {
+        self.symbols.get_mut(&symbol_ref).unwrap().captures = captures;
+    }
+
+    pub fn set_fields(&mut self, symbol_ref: SymbolRef, fields: Vec<SymbolRef>) {
+        self.symbols.get_mut(&symbol_ref).unwrap().fields = fields;
+    }
+
+    pub fn set_builtin(&mut self, symbol_ref: SymbolRef) {
+        self.symbols.get_mut(&symbol_ref).unwrap().builtin = true;
+    }
+
+    pub fn set_members(&mut self, symbol_ref: SymbolRef, scope: ScopeRef) {
+        self.symbols.get_mut(&symbol_ref).unwrap().members = Some(scope);
+    }
+
+    pub fn module(&self, path: &str) -> Option<ScopeRef> {
+        self.modules.get(path).copied()
+    }
+
+    pub fn add_module(&mut self, path: &str) -> ScopeRef {
+        let scope = self.add_scope(ScopeKind::Module, self.global_scope());
+        self.modules.insert(path.to_string(), scope);
+        scope
+    }
+
+    // Makes `symbol` reachable under another name in `scope`, which is how
+    // a field is found by its definite form.
+    pub fn alias(&mut self, scope: ScopeRef, name: &str, symbol_ref: SymbolRef) {
+        self.scopes[scope]
+            .symbols
+            .entry(name.to_string())
+            .or_insert(symbol_ref);
+    }
+
+    // Finds a field of the structure by its name or one of its aliases. The
+    // fields all live in the scope of the structure.
+    pub fn lookup_field(&self, struct_ref: SymbolRef, name: &str) -> Option<SymbolRef> {
+        let field = *self.get(struct_ref).fields.first()?;
+        self.lookup_in_scope(self.get(field).scope, name)
+    }
+
+    pub fn lookup_in_scope(&self, scope: ScopeRef, name: &str) -> Option<SymbolRef> {
+        self.scopes[scope].symbols.get(name).copied()
+    }
+
+    // Finds the innermost symbol called `name` of the given kind that is
+    // visible from `scope`.
+    pub fn lookup(&self, scope: ScopeRef, name: &str, kind: &SymbolKind) -> Option<SymbolRef> {
+        let mut current = Some(scope);
+        while let Some(scope_ref) = current {
+            if let Some(symbol_ref) = self.lookup_in_scope(scope_ref, name) {
+                if self.get(symbol_ref).kind.satisfies(kind) {
+                    return Some(symbol_ref);
+                }
             }
+            current = self.scopes[scope_ref].parent;
         }
         None
     }
 
-    pub fn contains_name(&self, name: &str) -> bool {
-        for symbol in self.symbols.values() {
-            if symbol.name == name {
+    // Finds the symbol a `la` in `scope` assigns to. Blocks update variables of
+    // the enclosing blocks, but a mechanism never reaches past its own scope, so
+    // a `la` inside a mechanism body shadows a global instead of overwriting it.
+    // In the same way a global `la` shadows a built-in mechanism.
+    pub fn lookup_assignable(&self, scope: ScopeRef, name: &str) -> Option<SymbolRef> {
+        let mut current = Some(scope);
+        while let Some(scope_ref) = current {
+            if let Some(symbol_ref) = self.lookup_in_scope(scope_ref, name) {
+                return Some(symbol_ref);
+            }
+            if let ScopeKind::Mechanism | ScopeKind::Global = self.scopes[scope_ref].kind {
+                break;
+            }
+            current = self.scopes[scope_ref].parent;
+        }
+        None
+    }
+
+    // Whether the symbol lives in the global frame rather than in the frame of
+    // a mechanism call.
+    pub fn is_global(&self, symbol_ref: SymbolRef) -> bool {
+        let mut current = Some(self.get(symbol_ref).scope);
+        while let Some(scope_ref) = current {
+            if self.scopes[scope_ref].kind == ScopeKind::Mechanism {
+                return false;
+            }
+            current = self.scopes[scope_ref].parent;
+        }
+        true
+    }
+
+    // Whether `scope` is `ancestor` or nested somewhere inside it.
+    pub fn is_within(&self, scope: ScopeRef, ancestor: ScopeRef) -> bool {
+        let mut current = Some(scope);
+        while let Some(scope_ref) = current {
+            if scope_ref == ancestor {
                 return true;
             }
+            current = self.scopes[scope_ref].parent;
         }
         false
     }
 
+    // Finds the name of the given kind visible from `scope` that is closest to
+    // `name`, if any is close enough to plausibly be a typo.
+    pub fn closest_name(&self, scope: ScopeRef, name: &str, kind: &SymbolKind) -> Option<String> {
+        let length = name.chars().count();
+        let max_distance = (length / 3).max(1);
+        let mut candidates = Vec::new();
+        let mut current = Some(scope);
+        while let Some(scope_ref) = current {
+            for symbol_ref in self.scopes[scope_ref].symbols.values() {
+                let symbol = self.get(*symbol_ref);
+                if symbol.kind.satisfies(kind) {
+                    candidates.push((utils::edit_distance(name, &symbol.name), &symbol.name));
+                }
+            }
+            current = self.scopes[scope_ref].parent;
+        }
+        candidates
+            .into_iter()
+            .filter(|(distance, _)| *distance <= max_distance && *distance < length)
+            .min()
+            .map(|(_, name)| name.clone())
+    }
+
+    pub fn closest_field(&self, struct_ref: SymbolRef, name: &str) -> Option<String> {
+        let length = name.chars().count();
+        let max_distance = (length / 3).max(1);
+        self.get(struct_ref)
+            .fields
+            .iter()
+            .map(|field| &self.get(*field).name)
+            .map(|field| (utils::edit_distance(name, field), field))
+            .filter(|(distance, _)| *distance <= max_distance && *distance < length)
+            .min()
+            .map(|(_, field)| field.clone())
+    }
+
     pub fn write_to_file(&self, filename: &str) -> Result<(), Box<dyn Error>> {
         let mut file = File::create(filename)?;
         writeln!(file, "Symbol_table\n------------")?;
-        for symbol in self.symbols.values() {
-            writeln!(
+        self.write_scope(&mut file, self.builtin_scope(), 0)?;
+        Ok(())
+    }
+
+    fn write_scope(
+        &self,
+        file: &mut File,
+        scope_ref: ScopeRef,
+        depth: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let indent = "    ".repeat(depth);
+        let scope = &self.scopes[scope_ref];
+        writeln!(file, "{}scope {} ({:?})", indent, scope_ref, scope.kind)?;
+        let mut symbol_refs: Vec<&SymbolRef> = scope.symbols.values().collect();
+        symbol_refs.sort();
+        symbol_refs.dedup();
+        for symbol_ref in symbol_refs {
+            let symbol = self.get(*symbol_ref);
+            write!(
                 file,
-                "name = {}, kind = {:?}, type = {:?}",
-                symbol.name, symbol.kind, symbol.type_
+                "{}    name = {}, kind = {:?}, type = {:?}",
+                indent, symbol.name, symbol.kind, symbol.type_
             )?;
+            if let Some(declared_type) = &symbol.declared_type {
+                write!(file, ", declared = {:?}", declared_type)?;
+            }
+            if symbol.builtin {
+                write!(file, ", builtin")?;
+            }
+            if let Some(members) = symbol.members {
+                write!(file, ", members = scope {}", members)?;
+            }
+            if !symbol.parameters.is_empty() {
+                let names: Vec<&str> = symbol
+                    .parameters
+                    .iter()
+                    .map(|parameter| self.get(*parameter).name.as_str())
+                    .collect();
+                write!(file, ", parameters = [{}]", names.join(", "))?;
+            }
+            if !symbol.fields.is_empty() {
+                let names: Vec<&str> = symbol
+                    .fields
+                    .iter()
+                    .map(|field| self.get(*field).name.as_str())
+                    .collect();
+                write!(file, ", fields = [{}]", names.join(", "))?;
+            }
+            if !symbol.captures.is_empty() {
+                let names: Vec<&str> = symbol
+                    .captures
+                    .iter()
+                    .map(|captured| self.get(*captured).name.as_str())
+                    .collect();
+                write!(file, ", captures = [{}]", names.join(", "))?;
+            }
+            writeln!(file)?;
+        }
+        for child in &scope.children {
+            self.write_scope(file, *child, depth + 1)?;
         }
         Ok(())
     }
@@ -101,7 +472,12 @@ impl StringList {
         }
     }
 
+    // The same text written twice shares one entry, so equal string literals
+    // have the same index.
     pub fn add(&mut self, s: &str) -> usize {
+        if let Some(index) = self.strings.iter().position(|string| string == s) {
+            return index;
+        }
         self.strings.push(s.to_string());
         self.strings.len() - 1
     }
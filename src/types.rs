@@ -0,0 +1,1130 @@
+use crate::{
+    ast::{self, Node, NodeType},
+    builtins,
+    diagnostic::Diagnostic,
+    lexer,
+    symbol::{SymbolKind, SymbolRef, SymbolTable, Type},
+    utils,
+};
+
+const TYPE_NAMES: [(&str, Type); 4] = [
+    ("tall", Type::Int),
+    ("desimaltall", Type::Float),
+    ("streng", Type::String),
+    ("sannhetsverdi", Type::Bool),
+];
+
+pub fn infer_types(ast: &mut Box<Node>, symbol_table: &mut SymbolTable) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    apply_declarations(ast, symbol_table, &mut diagnostics);
+    mark_void_mechanisms(ast, symbol_table, &mut diagnostics);
+
+    let mut checker = TypeChecker {
+        symbol_table,
+        diagnostics,
+        report: false,
+        changed: false,
+        loops: 0,
+    };
+    // Types flow from assignments to later uses and from `gi` to calls, which
+    // may come before the mechanism in the file. Types only ever go from
+    // unknown to known, so repeat until nothing new is learned and only then
+    // report what is still wrong.
+    loop {
+        checker.changed = false;
+        checker.check_statement(ast, None);
+        if !checker.changed {
+            break;
+        }
+    }
+    checker.report = true;
+    checker.check_statement(ast, None);
+    checker.diagnostics
+}
+
+fn parse_type_name(
+    annotation: &Node,
+    symbol_table: &SymbolTable,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Type> {
+    if let Some(symbol) = annotation.token.as_ref().and_then(symbol_of_token) {
+        return Some(Type::Struct {
+            name: symbol_table.get(symbol).name.clone(),
+            symbol,
+        });
+    }
+    for (name, type_) in TYPE_NAMES {
+        if annotation.data == name {
+            return Some(type_);
+        }
+    }
+    let token = annotation.token.as_ref()?;
+    let mut diagnostic = Diagnostic::at_token(
+        format!("ukjent type `{}`", annotation.data),
+        token,
+        annotation.data.chars().count(),
+    );
+    let closest = TYPE_NAMES
+        .iter()
+        .map(|(name, _)| (utils::edit_distance(&annotation.data, name), *name))
+        .min();
+    if let Some((distance, name)) = closest {
+        if distance <= 2 {
+            diagnostic = diagnostic.with_help(format!("mente du `{}`?", name));
+        }
+    }
+    diagnostics.push(diagnostic);
+    None
+}
+
+// Records the types written out with `som et tall` and on parameters as the
+// declared types of their symbols. A typed parameter is also known to hold a
+// value of that type inside the body, whatever the callers pass.
+fn apply_declarations(
+    ast: &Node,
+    symbol_table: &mut SymbolTable,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match ast.node_type {
+        NodeType::AssignmentStatement | NodeType::Parameter | NodeType::Field
+            if ast.children.last().unwrap().node_type == NodeType::TypeAnnotation =>
+        {
+            let annotation = ast.children.last().unwrap();
+            let identifier = &ast.children[0];
+            if let (Some(type_), Some(symbol_ref)) = (
+                parse_type_name(annotation, symbol_table, diagnostics),
+                symbol_of(identifier),
+            ) {
+                let symbol = symbol_table.get(symbol_ref);
+                match &symbol.declared_type {
+                    Some(declared_type) if *declared_type != type_ => {
+                        diagnostics.push(Diagnostic::at_token(
+                            format!(
+                                "`{}` er allerede erklært som {}",
+                                symbol.name, declared_type
+                            ),
+                            annotation.token.as_ref().unwrap(),
+                            annotation.data.chars().count(),
+                        ));
+                    }
+                    _ => {
+                        symbol_table.set_declared_type(symbol_ref, type_.clone());
+                        if ast.node_type != NodeType::AssignmentStatement {
+                            symbol_table.set_type(symbol_ref, type_);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {
+            for child in &ast.children {
+                apply_declarations(child, symbol_table, diagnostics);
+            }
+        }
+    }
+}
+
+// Mechanisms without any `gi` in their body give nothing back. Those with one
+// must give a value on every way through the body, or a call could end up
+// with nothing to use.
+fn mark_void_mechanisms(
+    ast: &Node,
+    symbol_table: &mut SymbolTable,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if ast.node_type == NodeType::FunctionDefinition {
+        if let Some(symbol) = symbol_of(&ast.children[0]) {
+            let body = &ast.children[2];
+            if !contains_return(body) {
+                symbol_table.set_type(symbol, Type::Void);
+            } else if !always_returns(body) {
+                let name = &symbol_table.get(symbol).name;
+                let token = ast.children[0].token.as_ref().unwrap();
+                diagnostics.push(
+                    Diagnostic::at_token(
+                        format!("mekanismen `{}` gir ikke en verdi på alle veier", name),
+                        token,
+                        name.chars().count(),
+                    )
+                    .with_help("avslutt mekanismen med en `gi`".to_string()),
+                );
+            }
+        }
+    }
+    for child in &ast.children {
+        mark_void_mechanisms(child, symbol_table, diagnostics);
+    }
+}
+
+// Whether every way through `ast` ends in a `gi` or an error. Loops may run
+// zero times, except `så lenge sant` without an `avbryt`.
+fn always_returns(ast: &Node) -> bool {
+    match ast.node_type {
+        NodeType::ReturnStatement | NodeType::Raise => true,
+        NodeType::StatementList | NodeType::Statement => {
+            ast.children.iter().any(|child| always_returns(child))
+        }
+        NodeType::IfStatement => {
+            ast.children.len() == 3 && ast.children[1..].iter().all(|body| always_returns(body))
+        }
+        NodeType::TryStatement => {
+            always_returns(&ast.children[0]) && always_returns(&ast.children[1])
+        }
+        // Without `ellers` no case may match.
+        NodeType::SwitchStatement => {
+            ast.children
+                .last()
+                .is_some_and(|case| case.children.len() == 1)
+                && ast.children[1..]
+                    .iter()
+                    .all(|case| always_returns(case.children.last().unwrap()))
+        }
+        NodeType::WhileStatement => {
+            ast::literal(&ast.children[0]) == Some(ast::Literal::Bool(true))
+                && !contains_break(&ast.children[1])
+        }
+        _ => false,
+    }
+}
+
+// A `avbryt` of the loop `ast` is the body of, not of one inside it.
+fn contains_break(ast: &Node) -> bool {
+    match ast.node_type {
+        NodeType::Break => true,
+        NodeType::WhileStatement
+        | NodeType::ForStatement
+        | NodeType::FunctionDefinition
+        | NodeType::AnonymousFunction => false,
+        _ => ast.children.iter().any(|child| contains_break(child)),
+    }
+}
+
+fn contains_return(ast: &Node) -> bool {
+    match ast.node_type {
+        NodeType::ReturnStatement => true,
+        NodeType::FunctionDefinition | NodeType::AnonymousFunction => false,
+        _ => ast.children.iter().any(|child| contains_return(child)),
+    }
+}
+
+fn symbol_of(node: &Node) -> Option<SymbolRef> {
+    node.token.as_ref().and_then(symbol_of_token)
+}
+
+fn symbol_of_token(token: &lexer::Token) -> Option<SymbolRef> {
+    match token.token_type() {
+        lexer::TokenType::Symbol(symbol) => Some(*symbol),
+        _ => None,
+    }
+}
+
+// The leftmost token of an expression, used to point at it in diagnostics.
+pub fn first_token(node: &Node) -> Option<&lexer::Token> {
+    match node.children.first() {
+        Some(child) => first_token(child),
+        None => node.token.as_ref(),
+    }
+}
+
+pub fn operator_name(operator: &str) -> &str {
+    match operator {
+        "+" => "pluss",
+        "-" => "minus",
+        "*" => "ganger",
+        "/" => "delt på",
+        "%" => "rest av",
+        "//" => "kvotienten av",
+        "^" => "opphøyd i",
+        "==" => "er lik",
+        "<" => "er mindre enn",
+        "<=" => "er mindre enn eller lik",
+        ">" => "er større enn",
+        ">=" => "er større enn eller lik",
+        _ => operator,
+    }
+}
+
+fn binary_type(operator: &str, left: &Type, right: &Type) -> Option<Type> {
+    if *left == Type::Unknown || *right == Type::Unknown {
+        return Some(Type::Unknown);
+    }
+    match (left, right) {
+        (Type::Int, Type::Int) => Some(Type::Int),
+        _ if left.is_numeric() && right.is_numeric() => Some(Type::Float),
+        (Type::String, Type::String) if operator == "+" => Some(Type::String),
+        _ => None,
+    }
+}
+
+// Any two values of the same kind can be compared for equality, except
+// mechanisms, but only numbers and strings have an order.
+fn comparable(operator: &str, left: &Type, right: &Type) -> bool {
+    if *left == Type::Unknown || *right == Type::Unknown {
+        return true;
+    }
+    let same_kind = left.merge(right).is_some() || (left.is_numeric() && right.is_numeric());
+    match operator {
+        "==" => same_kind && !matches!(left, Type::Mechanism(..)),
+        _ => same_kind && matches!(left, Type::Int | Type::Float | Type::String),
+    }
+}
+
+fn diagnostic(message: String, token: Option<&lexer::Token>, length: usize) -> Diagnostic {
+    match token {
+        Some(token) => Diagnostic::at_token(message, token, length),
+        None => Diagnostic::new(message, 0, 0, 0),
+    }
+}
+
+struct TypeChecker<'a> {
+    symbol_table: &'a mut SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+    report: bool,
+    changed: bool,
+    // How many loops the statement being checked is inside of, within the
+    // current mechanism.
+    loops: usize,
+}
+
+impl TypeChecker<'_> {
+    fn error(&mut self, message: String, token: Option<&lexer::Token>, length: usize) {
+        if self.report {
+            self.diagnostics.push(diagnostic(message, token, length));
+        }
+    }
+
+    fn warning(&mut self, message: String, token: Option<&lexer::Token>, length: usize) {
+        if self.report {
+            self.diagnostics
+                .push(diagnostic(message, token, length).as_warning());
+        }
+    }
+
+    fn check_statement(&mut self, node: &mut Box<Node>, function: Option<SymbolRef>) {
+        match node.node_type {
+            NodeType::AssignmentStatement => {
+                // An answer is read as a number when it goes into a number.
+                if node.children[1].node_type == NodeType::Read {
+                    if let Some(symbol) = symbol_of(&node.children[0]) {
+                        if let Some(declared_type) = &self.symbol_table.get(symbol).declared_type {
+                            node.children[1].type_ = declared_type.clone();
+                        }
+                    }
+                }
+                let type_ = self.check_value(&mut node.children[1]);
+                if let Some(symbol) = symbol_of(&node.children[0]) {
+                    let token = node.children[0].token.clone();
+                    self.unify(symbol, type_, token.as_ref());
+                }
+            }
+            NodeType::PrintStatement => {
+                self.check_value(&mut node.children[0]);
+            }
+            NodeType::ReturnStatement => {
+                let type_ = self.check_value(&mut node.children[0]);
+                let token = node.token.clone();
+                match function {
+                    Some(symbol) => self.unify(symbol, type_, token.as_ref()),
+                    None => self.error(
+                        "`gi` kan bare brukes inne i en mekanisme".to_string(),
+                        token.as_ref(),
+                        2,
+                    ),
+                }
+            }
+            NodeType::IfStatement => {
+                self.check_condition(&mut node.children[0]);
+                for body in node.children.iter_mut().skip(1) {
+                    self.check_statement(body, function);
+                }
+            }
+            NodeType::ForStatement => {
+                match self.check_value(&mut node.children[1]) {
+                    // An `oppslag` is gone through key by key.
+                    Type::List(element) | Type::Map(element, _) => {
+                        if let Some(symbol) = symbol_of(&node.children[0]) {
+                            let token = node.children[0].token.clone();
+                            self.unify(symbol, *element, token.as_ref());
+                        }
+                    }
+                    Type::Unknown => {}
+                    type_ => {
+                        let token = first_token(&node.children[1]).cloned();
+                        self.error(
+                            format!(
+                                "`for hvert` kan bare gå gjennom lister og oppslag, ikke {}",
+                                type_
+                            ),
+                            token.as_ref(),
+                            1,
+                        );
+                    }
+                }
+                self.loops += 1;
+                self.check_statement(&mut node.children[2], function);
+                self.loops -= 1;
+            }
+            NodeType::WhileStatement => {
+                self.check_condition(&mut node.children[0]);
+                self.loops += 1;
+                self.check_statement(&mut node.children[1], function);
+                self.loops -= 1;
+            }
+            // The first case that matches is taken, so a literal that was
+            // already given further up can never be.
+            NodeType::SwitchStatement => {
+                let subject = self.check_value(&mut node.children[0]);
+                let mut seen = Vec::new();
+                for case in node.children.iter_mut().skip(1) {
+                    let (body, values) = case.children.split_last_mut().unwrap();
+                    for value in values {
+                        let type_ = self.check_value(value);
+                        let token = first_token(value).cloned();
+                        if !comparable("==", &subject, &type_) {
+                            self.error(
+                                format!("kan ikke sammenligne {} og {}", subject, type_),
+                                token.as_ref(),
+                                1,
+                            );
+                        }
+                        if let Some(literal) = ast::literal(value) {
+                            if seen.contains(&literal) {
+                                self.warning(
+                                    "tilfellet er allerede dekket lenger opp og blir aldri valgt"
+                                        .to_string(),
+                                    token.as_ref(),
+                                    1,
+                                );
+                            }
+                            seen.push(literal);
+                        }
+                    }
+                    self.check_statement(body, function);
+                }
+            }
+            NodeType::Break | NodeType::Continue if self.loops == 0 => {
+                let (name, length) = match node.node_type {
+                    NodeType::Break => ("avbryt", 6),
+                    _ => ("hopp videre", 11),
+                };
+                let token = node.token.clone();
+                self.error(
+                    format!("`{}` kan bare brukes inne i en løkke", name),
+                    token.as_ref(),
+                    length,
+                );
+            }
+            // A loop around the definition is not around the calls.
+            NodeType::FunctionDefinition => {
+                let symbol = symbol_of(&node.children[0]);
+                let loops = std::mem::take(&mut self.loops);
+                self.check_statement(&mut node.children[2], symbol);
+                self.loops = loops;
+            }
+            NodeType::FunctionCall | NodeType::IndirectCall => {
+                self.check_expression(node);
+            }
+            NodeType::FileWrite => {
+                self.check_value(&mut node.children[0]);
+                self.check_file_name(&mut node.children[1]);
+            }
+            NodeType::TryStatement => {
+                if let Some(symbol) = node.children.get(2).and_then(|name| symbol_of(name)) {
+                    let token = node.children[2].token.clone();
+                    self.unify(symbol, Type::String, token.as_ref());
+                }
+                self.check_statement(&mut node.children[0], function);
+                self.check_statement(&mut node.children[1], function);
+            }
+            NodeType::Assert => self.check_condition(&mut node.children[0]),
+            // Any value can be the message, it is shown as `si` would.
+            NodeType::Raise => {
+                self.check_value(&mut node.children[0]);
+            }
+            NodeType::ListAppend => self.check_append(node),
+            NodeType::MapInsert => self.check_insert(node),
+            NodeType::FieldAssignment => {
+                let value = self.check_value(&mut node.children[1]);
+                self.check_field_access(&mut node.children[0]);
+                if let Some(field) = symbol_of(&node.children[0].children[0]) {
+                    let token = node.children[0].children[0].token.clone();
+                    self.unify(field, value, token.as_ref());
+                }
+            }
+            _ => {
+                for child in &mut node.children {
+                    self.check_statement(child, function);
+                }
+            }
+        }
+    }
+
+    // Records that `symbol` holds, or for a mechanism gives, values of `type_`,
+    // checking it against the declared type or what was inferred before.
+    fn unify(&mut self, symbol_ref: SymbolRef, type_: Type, token: Option<&lexer::Token>) {
+        if type_ == Type::Unknown {
+            return;
+        }
+        let symbol = self.symbol_table.get(symbol_ref).clone();
+        let merged = symbol.type_.merge(&type_);
+        if let Some(merged) = &merged {
+            if *merged != symbol.type_ {
+                self.symbol_table.set_type(symbol_ref, merged.clone());
+                self.changed = true;
+            }
+        }
+        if let Some(declared_type) = &symbol.declared_type {
+            if declared_type.merge(&type_).as_ref() != Some(declared_type) {
+                self.error(
+                    format!(
+                        "`{}` er erklært som {} og kan ikke få en verdi av typen {}",
+                        symbol.name, declared_type, type_
+                    ),
+                    token,
+                    symbol.name.chars().count(),
+                );
+            }
+            return;
+        }
+        if merged.is_some() {
+            return;
+        }
+        match symbol.kind {
+            SymbolKind::Variable | SymbolKind::Constant => self.error(
+                format!(
+                    "`{}` har typen {} og kan ikke få en verdi av typen {}",
+                    symbol.name, symbol.type_, type_
+                ),
+                token,
+                symbol.name.chars().count(),
+            ),
+            SymbolKind::Field => self.error(
+                format!(
+                    "feltet `{}` har typen {} og kan ikke få en verdi av typen {}",
+                    symbol.name, symbol.type_, type_
+                ),
+                token,
+                symbol.name.chars().count(),
+            ),
+            SymbolKind::Function => self.error(
+                format!(
+                    "mekanismen `{}` gir både {} og {}",
+                    symbol.name, symbol.type_, type_
+                ),
+                token,
+                2,
+            ),
+            SymbolKind::Struct | SymbolKind::Module => unreachable!(),
+        }
+    }
+
+    // Checks an expression whose value is used, which rules out calls to
+    // mechanisms that give nothing back.
+    fn check_value(&mut self, node: &mut Box<Node>) -> Type {
+        let type_ = self.check_expression(node);
+        if type_ == Type::Void {
+            let token = first_token(node).cloned();
+            let length = match token.as_ref().map(|token| token.token_type()) {
+                Some(lexer::TokenType::Symbol(symbol)) => {
+                    self.symbol_table.get(*symbol).name.chars().count()
+                }
+                _ => 1,
+            };
+            self.error(
+                "mekanismen gir ingen verdi som kan brukes her".to_string(),
+                token.as_ref(),
+                length,
+            );
+            return Type::Unknown;
+        }
+        type_
+    }
+
+    // Checks the arguments of a call against the parameters of the mechanism
+    // and gives the type of its result.
+    fn check_call(&mut self, node: &mut Box<Node>, symbol_ref: SymbolRef) -> Type {
+        let symbol = self.symbol_table.get(symbol_ref).clone();
+        let arguments = node.children.len() - 1;
+        if arguments != symbol.parameters.len() {
+            let token = node.children[0].token.clone();
+            self.error(
+                format!(
+                    "mekanismen `{}` tar {} verdier, men fikk {}",
+                    symbol.name,
+                    symbol.parameters.len(),
+                    arguments
+                ),
+                token.as_ref(),
+                symbol.name.chars().count(),
+            );
+        }
+        if symbol.builtin {
+            return self.check_builtin_call(node, &symbol.name);
+        }
+        for (argument, parameter) in node.children.iter_mut().skip(1).zip(
+            symbol
+                .parameters
+                .iter()
+                .map(Some)
+                .chain(std::iter::repeat(None)),
+        ) {
+            let type_ = self.check_value(argument);
+            if let Some(parameter) = parameter {
+                let token = first_token(argument).cloned();
+                self.unify(*parameter, type_, token.as_ref());
+            }
+        }
+        symbol.type_
+    }
+
+    // A call of a mechanism held in a variable, checked against its type once
+    // that is known. The arguments decide the types of the parameters of every
+    // mechanism the variable may hold, as they do for a call by name.
+    fn check_indirect_call(&mut self, node: &mut Box<Node>) -> Type {
+        let callee = self.check_value(&mut node.children[0]);
+        let token = node.children[0].token.clone();
+        let name = match token.as_ref().map(|token| token.token_type()) {
+            Some(lexer::TokenType::Symbol(symbol)) => self.symbol_table.get(*symbol).name.clone(),
+            _ => String::new(),
+        };
+        let mut arguments = Vec::new();
+        for argument in node.children.iter_mut().skip(1) {
+            arguments.push((self.check_value(argument), first_token(argument).cloned()));
+        }
+        match callee {
+            Type::Mechanism(parameters, result, symbols) => {
+                if parameters.len() != arguments.len() {
+                    self.error(
+                        format!(
+                            "mekanismen i `{}` tar {} verdier, men fikk {}",
+                            name,
+                            parameters.len(),
+                            arguments.len()
+                        ),
+                        token.as_ref(),
+                        name.chars().count(),
+                    );
+                }
+                for symbol_ref in symbols {
+                    let symbol = self.symbol_table.get(symbol_ref).clone();
+                    let builtin = match symbol.builtin {
+                        true => builtins::find(&symbol.name),
+                        false => None,
+                    };
+                    for (i, (argument, token)) in arguments.iter().enumerate() {
+                        let Some(parameter) = symbol.parameters.get(i) else {
+                            break;
+                        };
+                        match &builtin {
+                            Some(builtin) if !builtin.accepts(i, argument) => self.error(
+                                format!(
+                                    "mekanismen i `{}` tar {}, men fikk {}",
+                                    name, builtin.parameters[i].1, argument
+                                ),
+                                token.as_ref(),
+                                1,
+                            ),
+                            Some(_) => {}
+                            None => self.unify(*parameter, argument.clone(), token.as_ref()),
+                        }
+                    }
+                }
+                *result
+            }
+            Type::Unknown => Type::Unknown,
+            type_ => {
+                self.error(
+                    format!("`{}` er {}, ikke en mekanisme", name, type_),
+                    token.as_ref(),
+                    name.chars().count(),
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    // The type of `symbol` as a value, from its parameters and what it gives.
+    fn mechanism_type(&self, symbol_ref: SymbolRef) -> Type {
+        let symbol = self.symbol_table.get(symbol_ref);
+        let parameters = symbol
+            .parameters
+            .iter()
+            .map(|parameter| self.symbol_table.get(*parameter).type_.clone())
+            .collect();
+        Type::Mechanism(parameters, Box::new(symbol.type_.clone()), vec![symbol_ref])
+    }
+
+    // Built-in mechanisms may take values of several types, so their arguments
+    // are only checked against the parameters and decide nothing.
+    fn check_builtin_call(&mut self, node: &mut Box<Node>, name: &str) -> Type {
+        let builtin = builtins::find(name).expect("Built-in mechanism missing from the registry");
+        let mut types = Vec::new();
+        for (i, argument) in node.children.iter_mut().skip(1).enumerate() {
+            let type_ = self.check_value(argument);
+            if i < builtin.parameters.len() && !builtin.accepts(i, &type_) {
+                let token = first_token(argument).cloned();
+                self.error(
+                    format!(
+                        "mekanismen `{}` kan ikke få {} som `{}`",
+                        name, type_, builtin.parameters[i].0
+                    ),
+                    token.as_ref(),
+                    1,
+                );
+            }
+            types.push(type_);
+        }
+        (builtin.result)(&types)
+    }
+
+    fn check_condition(&mut self, node: &mut Box<Node>) {
+        let type_ = self.check_value(node);
+        if type_ != Type::Unknown && type_ != Type::Bool {
+            let token = first_token(node).cloned();
+            self.error(
+                format!("betingelsen må være en sannhetsverdi, men er {}", type_),
+                token.as_ref(),
+                1,
+            );
+        }
+    }
+
+    fn check_file_name(&mut self, node: &mut Box<Node>) {
+        let type_ = self.check_value(node);
+        if type_ != Type::Unknown && type_ != Type::String {
+            let token = first_token(node).cloned();
+            self.error(
+                format!("navnet på en fil må være en streng, ikke {}", type_),
+                token.as_ref(),
+                1,
+            );
+        }
+    }
+
+    // Appending to a list whose elements are not known yet, such as one made
+    // with `tom liste`, decides what the list holds.
+    fn check_append(&mut self, node: &mut Box<Node>) {
+        let value = self.check_value(&mut node.children[0]);
+        let list = self.check_value(&mut node.children[1]);
+        match &list {
+            Type::List(element) => match element.merge(&value) {
+                Some(merged) => {
+                    if let Some(symbol) = symbol_of(&node.children[1]) {
+                        let token = node.children[1].token.clone();
+                        self.unify(symbol, Type::List(Box::new(merged)), token.as_ref());
+                    }
+                }
+                None => {
+                    let token = first_token(&node.children[0]).cloned();
+                    self.error(
+                        format!("kan ikke legge {} til {}", value, list),
+                        token.as_ref(),
+                        1,
+                    );
+                }
+            },
+            Type::Unknown => {}
+            _ => {
+                let token = node.token.clone();
+                self.error(
+                    format!("kan bare legge til i lister, ikke i {}", list),
+                    token.as_ref(),
+                    4,
+                );
+            }
+        }
+    }
+
+    fn check_list_literal(&mut self, node: &mut Box<Node>) -> Type {
+        let mut element_type = Type::Unknown;
+        for element in node.children.iter_mut() {
+            let type_ = self.check_value(element);
+            match element_type.merge(&type_) {
+                Some(merged) => element_type = merged,
+                None => {
+                    let token = first_token(element).cloned();
+                    self.error(
+                        format!(
+                            "alle elementene i en liste må ha samme type, men fant både {} og {}",
+                            element_type, type_
+                        ),
+                        token.as_ref(),
+                        1,
+                    );
+                }
+            }
+        }
+        Type::List(Box::new(element_type))
+    }
+
+    // `element` takes a position in a list or a key in an `oppslag`.
+    fn check_element(&mut self, node: &mut Box<Node>) -> Type {
+        let index = self.check_value(&mut node.children[0]);
+        let token = first_token(&node.children[0]).cloned();
+        match self.check_value(&mut node.children[1]) {
+            Type::List(element) => {
+                if index != Type::Unknown && index != Type::Int {
+                    self.error(
+                        format!("plassen i listen må være et tall, men er {}", index),
+                        token.as_ref(),
+                        1,
+                    );
+                }
+                *element
+            }
+            Type::Map(key, value) => {
+                if !index.is_key() {
+                    self.check_key(&index, token.as_ref());
+                } else if key.merge(&index).is_none() {
+                    self.error(
+                        format!("nøkkelen må være {}, men er {}", key, index),
+                        token.as_ref(),
+                        1,
+                    );
+                }
+                *value
+            }
+            Type::Unknown => Type::Unknown,
+            list => {
+                let token = node.token.clone();
+                self.error(
+                    format!(
+                        "`element` kan bare brukes på lister og oppslag, ikke på {}",
+                        list
+                    ),
+                    token.as_ref(),
+                    7,
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_key(&mut self, key: &Type, token: Option<&lexer::Token>) {
+        if !key.is_key() {
+            self.error(
+                format!(
+                    "nøkler må være tall, strenger eller sannhetsverdier, ikke {}",
+                    key
+                ),
+                token,
+                1,
+            );
+        }
+    }
+
+    fn check_map_literal(&mut self, node: &mut Box<Node>) -> Type {
+        let mut key_type = Type::Unknown;
+        let mut value_type = Type::Unknown;
+        for entry in node.children.iter_mut() {
+            let key = self.check_value(&mut entry.children[0]);
+            let value = self.check_value(&mut entry.children[1]);
+            let key_token = first_token(&entry.children[0]).cloned();
+            let value_token = first_token(&entry.children[1]).cloned();
+            self.check_key(&key, key_token.as_ref());
+            match key_type.merge(&key) {
+                Some(merged) => key_type = merged,
+                None => self.error(
+                    format!(
+                        "alle nøklene i et oppslag må ha samme type, men fant både {} og {}",
+                        key_type, key
+                    ),
+                    key_token.as_ref(),
+                    1,
+                ),
+            }
+            match value_type.merge(&value) {
+                Some(merged) => value_type = merged,
+                None => self.error(
+                    format!(
+                        "alle verdiene i et oppslag må ha samme type, men fant både {} og {}",
+                        value_type, value
+                    ),
+                    value_token.as_ref(),
+                    1,
+                ),
+            }
+        }
+        Type::Map(Box::new(key_type), Box::new(value_type))
+    }
+
+    // Like appending to a list, inserting into an empty `oppslag` decides
+    // the types of its keys and values.
+    fn check_insert(&mut self, node: &mut Box<Node>) {
+        let key = self.check_value(&mut node.children[0]);
+        let value = self.check_value(&mut node.children[1]);
+        let map = self.check_value(&mut node.children[2]);
+        match &map {
+            Type::Map(_, _) if !key.is_key() => {
+                let token = first_token(&node.children[0]).cloned();
+                self.check_key(&key, token.as_ref());
+            }
+            Type::Map(key_type, value_type) => {
+                match (key_type.merge(&key), value_type.merge(&value)) {
+                    (Some(key), Some(value)) => {
+                        if let Some(symbol) = symbol_of(&node.children[2]) {
+                            let token = node.children[2].token.clone();
+                            self.unify(
+                                symbol,
+                                Type::Map(Box::new(key), Box::new(value)),
+                                token.as_ref(),
+                            );
+                        }
+                    }
+                    _ => {
+                        let token = first_token(&node.children[0]).cloned();
+                        self.error(
+                            format!("kan ikke legge {} som {} til {}", key, value, map),
+                            token.as_ref(),
+                            1,
+                        );
+                    }
+                }
+            }
+            Type::Unknown => {}
+            _ => {
+                let token = node.token.clone();
+                self.error(
+                    format!("kan bare legge nøkler til i oppslag, ikke i {}", map),
+                    token.as_ref(),
+                    4,
+                );
+            }
+        }
+    }
+
+    // `finnes x i y` looks for an element of a list or a key of an `oppslag`.
+    fn check_contains(&mut self, node: &mut Box<Node>) -> Type {
+        let item = self.check_value(&mut node.children[0]);
+        let expected = match self.check_value(&mut node.children[1]) {
+            Type::List(element) => *element,
+            Type::Map(_, _) if !item.is_key() => {
+                let token = first_token(&node.children[0]).cloned();
+                self.check_key(&item, token.as_ref());
+                return Type::Bool;
+            }
+            Type::Map(key, _) => *key,
+            Type::Unknown => Type::Unknown,
+            container => {
+                let token = node.token.clone();
+                self.error(
+                    format!(
+                        "`finnes` kan bare brukes på lister og oppslag, ikke på {}",
+                        container
+                    ),
+                    token.as_ref(),
+                    6,
+                );
+                Type::Unknown
+            }
+        };
+        if !comparable("==", &item, &expected) {
+            let token = first_token(&node.children[0]).cloned();
+            self.error(
+                format!("kan ikke lete etter {} blant {}", item, expected),
+                token.as_ref(),
+                1,
+            );
+        }
+        Type::Bool
+    }
+
+    fn check_struct_literal(&mut self, node: &mut Box<Node>) -> Type {
+        for field_value in node.children.iter_mut().skip(1) {
+            let type_ = self.check_value(&mut field_value.children[1]);
+            if let Some(field) = symbol_of(&field_value.children[0]) {
+                let token = first_token(&field_value.children[1]).cloned();
+                self.unify(field, type_, token.as_ref());
+            }
+        }
+        match symbol_of(&node.children[0]) {
+            Some(symbol) => Type::Struct {
+                name: self.symbol_table.get(symbol).name.clone(),
+                symbol,
+            },
+            None => Type::Unknown,
+        }
+    }
+
+    // Finds the field named in `alderen til p` among the fields of the type of
+    // `p`, replacing the name with the field symbol once it is known.
+    fn check_field_access(&mut self, node: &mut Box<Node>) -> Type {
+        let structure = self.check_value(&mut node.children[1]);
+        let token = node.children[0].token.clone().unwrap();
+        let name = match token.token_type() {
+            lexer::TokenType::Symbol(field) => {
+                return self.symbol_table.get(*field).type_.clone();
+            }
+            lexer::TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        match structure {
+            Type::Struct {
+                name: struct_name,
+                symbol,
+            } => match self.symbol_table.lookup_field(symbol, &name) {
+                Some(field) => {
+                    node.children[0].token = Some(lexer::Token::new(
+                        lexer::TokenType::Symbol(field),
+                        token.line(),
+                        token.column(),
+                    ));
+                    self.changed = true;
+                    self.symbol_table.get(field).type_.clone()
+                }
+                None => {
+                    if self.report {
+                        let mut diagnostic = Diagnostic::at_token(
+                            format!("`{}` har ikke feltet `{}`", struct_name, name),
+                            &token,
+                            name.chars().count(),
+                        );
+                        if let Some(suggestion) = self.symbol_table.closest_field(symbol, &name) {
+                            diagnostic =
+                                diagnostic.with_help(format!("mente du `{}`?", suggestion));
+                        }
+                        self.diagnostics.push(diagnostic);
+                    }
+                    Type::Unknown
+                }
+            },
+            Type::Unknown => {
+                self.error(
+                    format!("vet ikke hvilken struktur `{}` hører til", name),
+                    Some(&token),
+                    name.chars().count(),
+                );
+                Type::Unknown
+            }
+            type_ => {
+                self.error(
+                    format!(
+                        "`{} til` kan bare brukes på strukturer, ikke på {}",
+                        name, type_
+                    ),
+                    Some(&token),
+                    name.chars().count(),
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_expression(&mut self, node: &mut Box<Node>) -> Type {
+        let type_ = match node.node_type {
+            NodeType::Negation => {
+                let type_ = self.check_value(&mut node.children[0]);
+                if type_ == Type::Unknown || type_.is_numeric() {
+                    type_
+                } else {
+                    let token = node.token.clone();
+                    self.error(
+                        format!("kan ikke bruke `minus` på {}", type_),
+                        token.as_ref(),
+                        5,
+                    );
+                    Type::Unknown
+                }
+            }
+            NodeType::Read => {
+                self.check_value(&mut node.children[0]);
+                match node.type_ {
+                    Type::Int | Type::Float => node.type_.clone(),
+                    _ => Type::String,
+                }
+            }
+            NodeType::FileRead => {
+                self.check_file_name(&mut node.children[0]);
+                match node.data.as_str() {
+                    "linjene" => Type::List(Box::new(Type::String)),
+                    _ => Type::String,
+                }
+            }
+            NodeType::StructLiteral => self.check_struct_literal(node),
+            NodeType::FieldAccess => self.check_field_access(node),
+            NodeType::ListLiteral => self.check_list_literal(node),
+            NodeType::Element => self.check_element(node),
+            NodeType::MapLiteral => self.check_map_literal(node),
+            NodeType::Contains => self.check_contains(node),
+            NodeType::ListLength => {
+                match self.check_value(&mut node.children[0]) {
+                    Type::List(_) | Type::Map(..) | Type::String | Type::Unknown => {}
+                    type_ => {
+                        let token = node.token.clone();
+                        self.error(
+                            format!(
+                                "`lengden av` kan bare brukes på lister, oppslag og strenger, ikke på {}",
+                                type_
+                            ),
+                            token.as_ref(),
+                            7,
+                        );
+                    }
+                }
+                Type::Int
+            }
+            NodeType::FunctionCall => match symbol_of(&node.children[0]) {
+                Some(symbol) => self.check_call(node, symbol),
+                None => Type::Unknown,
+            },
+            NodeType::IndirectCall => self.check_indirect_call(node),
+            NodeType::FunctionReference => match symbol_of(node) {
+                Some(symbol) => self.mechanism_type(symbol),
+                None => Type::Unknown,
+            },
+            // The body is a `gi` of the value after `gir`.
+            NodeType::AnonymousFunction => match symbol_of(node) {
+                Some(symbol) => {
+                    let loops = std::mem::take(&mut self.loops);
+                    self.check_statement(&mut node.children[1], Some(symbol));
+                    self.loops = loops;
+                    self.mechanism_type(symbol)
+                }
+                None => Type::Unknown,
+            },
+            NodeType::Condition => {
+                let left = self.check_value(&mut node.children[0]);
+                let right = self.check_value(&mut node.children[1]);
+                if !comparable(&node.data, &left, &right) {
+                    let operator = operator_name(&node.data).to_string();
+                    self.error(
+                        format!("kan ikke sammenligne {} og {}", left, right),
+                        node.token.as_ref(),
+                        operator.chars().count(),
+                    );
+                }
+                Type::Bool
+            }
+            _ if node.children.len() == 2 => {
+                let left = self.check_value(&mut node.children[0]);
+                let right = self.check_value(&mut node.children[1]);
+                match binary_type(&node.data, &left, &right) {
+                    Some(type_) => type_,
+                    None => {
+                        let operator = operator_name(&node.data).to_string();
+                        self.error(
+                            format!("kan ikke bruke `{}` på {} og {}", operator, left, right),
+                            node.token.as_ref(),
+                            operator.chars().count(),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            _ => match node.token.as_ref().map(|token| token.token_type()) {
+                Some(lexer::TokenType::IntLiteral(_) | lexer::TokenType::BigIntLiteral(_)) => {
+                    Type::Int
+                }
+                Some(lexer::TokenType::FloatLiteral(_)) => Type::Float,
+                Some(lexer::TokenType::BoolLiteral(_)) => Type::Bool,
+                Some(lexer::TokenType::StringListIndex(_)) => Type::String,
+                Some(lexer::TokenType::Symbol(symbol)) => {
+                    self.symbol_table.get(*symbol).type_.clone()
+                }
+                _ => Type::Unknown,
+            },
+        };
+        node.type_ = type_.clone();
+        type_
+    }
+}
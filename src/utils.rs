@@ -1,10 +1,5 @@
 use std::error::Error;
 
-pub fn read_file(filename: &str) -> Result<String, Box<dyn Error>> {
-    let contents = std::fs::read_to_string(filename)?;
-    Ok(contents)
-}
-
 pub fn graphwiz_to_png(dot_filename: &str, png_filename: &str) -> Result<(), Box<dyn Error>> {
     let dot = std::process::Command::new("dot")
         .arg("-Tpng")
@@ -13,13 +8,46 @@ pub fn graphwiz_to_png(dot_filename: &str, png_filename: &str) -> Result<(), Box
         .arg(png_filename)
         .output()?;
     if !dot.status.success() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!(
-                "Failed to convert dot file to png: {}",
-                String::from_utf8_lossy(&dot.stderr)
-            ),
-        )));
+        return Err(Box::new(std::io::Error::other(format!(
+            "Failed to convert dot file to png: {}",
+            String::from_utf8_lossy(&dot.stderr)
+        ))));
     }
     Ok(())
 }
+
+// The definite forms of a noun, `alder` -> `alderen` and `navn` -> `navnet`.
+// Both endings are given, since the gender of a name is not known.
+pub fn definite_forms(noun: &str) -> [String; 2] {
+    match noun.ends_with('e') {
+        true => [format!("{}n", noun), format!("{}t", noun)],
+        false => [format!("{}en", noun), format!("{}et", noun)],
+    }
+}
+
+// Edit distance between two strings, counted in characters. Swapping two
+// neighbouring characters counts as a single edit, so `hie` is one edit away
+// from `hei`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    distances[a.len()][b.len()]
+}
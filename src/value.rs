@@ -0,0 +1,335 @@
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt, rc::Rc};
+
+use crate::{bigint::BigInt, fraction::Fraction, symbol::SymbolRef};
+
+// A value as seen by the running program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    // A whole number too large for `Int`. Arithmetic only produces one when
+    // the result does not fit in an i64, so the two never hold the same number.
+    BigInt(BigInt),
+    // An exact result of `delt på` that is not a whole number. It only exists
+    // when the simulator divides exactly, see `simulate::Division`.
+    Fraction(Fraction),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    // Lists live on the heap and are shared, so `legg` through one variable
+    // is seen through every other variable holding the same list.
+    List(Rc<RefCell<Vec<Value>>>),
+    // Structures and maps are shared the same way.
+    Struct(Rc<RefCell<Record>>),
+    Map(Rc<RefCell<Map>>),
+    Mechanism(Rc<Mechanism>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+// A mechanism held as a value, either a named one or one made with
+// `mekanisme som`. The latter keeps a copy of the variables it reads from
+// outside itself, globals included, as they were when it was made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mechanism {
+    pub symbol: SymbolRef,
+    pub captured: HashMap<SymbolRef, Value>,
+}
+
+// The values that can be keys in a map, which are exactly those the type
+// checker allows. To it a fraction is a whole number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    BigInt(BigInt),
+    Fraction(Fraction),
+    Bool(bool),
+    String(String),
+}
+
+impl Key {
+    fn of(value: &Value) -> Option<Key> {
+        match value {
+            Value::Int(value) => Some(Key::Int(*value)),
+            Value::BigInt(value) => Some(Key::BigInt(value.clone())),
+            Value::Fraction(value) => Some(Key::Fraction(value.clone())),
+            Value::Bool(value) => Some(Key::Bool(*value)),
+            Value::String(value) => Some(Key::String(value.clone())),
+            _ => None,
+        }
+    }
+}
+
+// An `oppslag`, which keeps its entries in the order the keys were first
+// inserted so that going through it gives the same order every time.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    positions: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let position = self.positions.get(&Key::of(key)?)?;
+        Some(&self.entries[*position].1)
+    }
+
+    // A value that cannot be a key is never in the map.
+    pub fn contains_key(&self, key: &Value) -> bool {
+        Key::of(key).is_some_and(|key| self.positions.contains_key(&key))
+    }
+
+    // Replaces the value of a key already in the map without moving it.
+    pub fn insert(&mut self, key: Value, value: Value) {
+        let Some(map_key) = Key::of(&key) else {
+            panic!("{} cannot be a key", key);
+        };
+        match self.positions.get(&map_key) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(map_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Value {
+    pub fn new_list() -> Self {
+        Value::List(Rc::new(RefCell::new(Vec::new())))
+    }
+
+    pub fn new_map() -> Self {
+        Value::Map(Rc::new(RefCell::new(Map::default())))
+    }
+
+    // Keeps a whole number as an `Int` whenever it fits in one.
+    pub fn from_big_int(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(value) => Value::Int(value),
+            None => Value::BigInt(value),
+        }
+    }
+
+    // Reads a whole number written with digits and perhaps a sign, such as an
+    // answer to `svaret på`, as an `Int` when it fits like a literal would be.
+    pub fn parse_int(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return None;
+        }
+        let value = BigInt::from_digits(digits);
+        Some(Value::from_big_int(match negative {
+            true => value.negate(),
+            false => value,
+        }))
+    }
+
+    // Keeps a fraction as a whole number whenever it is one.
+    pub fn from_fraction(value: Fraction) -> Self {
+        match value.as_big_int() {
+            Some(value) => Value::from_big_int(value),
+            None => Value::Fraction(value),
+        }
+    }
+
+    pub fn as_fraction(&self) -> Option<Fraction> {
+        match self {
+            Value::Fraction(value) => Some(value.clone()),
+            _ => self.as_big_int().map(Fraction::from_big_int),
+        }
+    }
+
+    pub fn as_big_int(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(value) => Some(BigInt::from_i64(*value)),
+            Value::BigInt(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(value) => Some(*value as f64),
+            Value::BigInt(value) => Some(value.to_f64()),
+            Value::Fraction(value) => Some(value.to_f64()),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Int(value) => *value != 0,
+            Value::BigInt(value) => !value.is_zero(),
+            Value::Fraction(value) => !value.is_zero(),
+            Value::Float(value) => *value != 0.0,
+            Value::Bool(value) => *value,
+            Value::String(value) => !value.is_empty(),
+            Value::List(values) => !values.borrow().is_empty(),
+            Value::Struct(_) | Value::Mechanism(_) => true,
+            Value::Map(map) => !map.borrow().is_empty(),
+        }
+    }
+
+    // Equality as the language sees it, where `1` and `1.0` are the same number
+    // and lists, structures and maps are equal when what they hold is.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => left == right,
+            (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_), Value::BigInt(_)) => self.as_big_int() == other.as_big_int(),
+            (Value::Fraction(_), Value::Int(_) | Value::BigInt(_) | Value::Fraction(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Fraction(_)) => {
+                self.as_fraction() == other.as_fraction()
+            }
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::List(left), Value::List(right)) => {
+                let (left, right) = (left.borrow(), right.borrow());
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .zip(right.iter())
+                        .all(|(left, right)| left.equals(right))
+            }
+            (Value::Struct(left), Value::Struct(right)) => {
+                let (left, right) = (left.borrow(), right.borrow());
+                left.name == right.name
+                    && left
+                        .fields
+                        .iter()
+                        .zip(right.fields.iter())
+                        .all(|((_, left), (_, right))| left.equals(right))
+            }
+            (Value::Map(left), Value::Map(right)) => {
+                let (left, right) = (left.borrow(), right.borrow());
+                left.len() == right.len()
+                    && left
+                        .entries
+                        .iter()
+                        .all(|(key, value)| right.get(key).is_some_and(|other| value.equals(other)))
+            }
+            _ => match (self.as_float(), other.as_float()) {
+                (Some(left), Some(right)) => left == right,
+                _ => false,
+            },
+        }
+    }
+
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => Some(left.cmp(right)),
+            (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_), Value::BigInt(_)) => {
+                Some(self.as_big_int()?.cmp(&other.as_big_int()?))
+            }
+            (Value::Fraction(_), Value::Int(_) | Value::BigInt(_) | Value::Fraction(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Fraction(_)) => {
+                Some(self.as_fraction()?.cmp(&other.as_fraction()?))
+            }
+            (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+            _ => self.as_float()?.partial_cmp(&other.as_float()?),
+        }
+    }
+}
+
+impl Value {
+    // Shows the value with fractions written as decimal numbers, `0.5`
+    // instead of `1/2`.
+    pub fn with_decimal_fractions(&self) -> impl fmt::Display + '_ {
+        Shown {
+            value: self,
+            decimal_fractions: true,
+        }
+    }
+}
+
+struct Shown<'a> {
+    value: &'a Value,
+    decimal_fractions: bool,
+}
+
+impl Shown<'_> {
+    fn of<'a>(&self, value: &'a Value) -> Shown<'a> {
+        Shown {
+            value,
+            decimal_fractions: self.decimal_fractions,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Shown {
+            value: self,
+            decimal_fractions: false,
+        }
+        .fmt(f)
+    }
+}
+
+impl fmt::Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::BigInt(value) => write!(f, "{}", value),
+            Value::Fraction(value) if self.decimal_fractions => write!(f, "{}", value.to_f64()),
+            Value::Fraction(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Bool(true) => write!(f, "sant"),
+            Value::Bool(false) => write!(f, "usant"),
+            Value::String(value) => write!(f, "{}", value),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", self.of(value))?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", self.of(key), self.of(value))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Struct(record) => {
+                let record = record.borrow();
+                write!(f, "{}(", record.name)?;
+                for (i, (name, value)) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, self.of(value))?;
+                }
+                write!(f, ")")
+            }
+            Value::Mechanism(_) => write!(f, "mekanisme"),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// Runs every program in `examples/` and compares what it writes with the
+// expected output in `tests/golden/`. A program that reads answers gets them
+// from a `.in` file next to its expected output. The programs in
+// `examples/feil/` must stop with an error, the others must not. Files that
+// are only used by other programs go in directories of their own.
+//
+// Run with `UPDATE_GOLDEN=1` to write the expected output from the current
+// behaviour instead of comparing with it.
+
+use std::{fs, path::Path};
+
+use norsk::{compile, compile_file, Capture, Division, Options};
+
+// Any warnings and everything the program writes, followed by the error that
+// stopped it as it would appear in a terminal, and whether there was one.
+fn run(path: &Path, source: &str, input: &str) -> (String, bool) {
+    let program = match compile_file(path, source) {
+        Ok(program) => program,
+        Err(diagnostics) => {
+            let output = diagnostics
+                .iter()
+                .map(|diagnostic| format!("{}\n", diagnostic.render(source)))
+                .collect();
+            return (output, true);
+        }
+    };
+    let mut output: String = program
+        .warnings()
+        .iter()
+        .map(|warning| format!("{}\n", warning.render(source)))
+        .collect();
+    let mut console = Capture::with_input(input);
+    let result = program.run(Options::default(), &mut console);
+    output += console.output();
+    if let Err(error) = &result {
+        output += &error.render(source);
+    }
+    (output, result.is_err())
+}
+
+fn check_directory(directory: &str, should_fail: bool, failures: &mut Vec<String>) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files: Vec<_> = fs::read_dir(root.join("examples").join(directory))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "nb"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no examples in examples/{}", directory);
+
+    for path in files {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let golden = root.join("tests/golden").join(directory).join(name);
+        let source = fs::read_to_string(&path).unwrap();
+        let input = fs::read_to_string(golden.with_extension("in")).unwrap_or_default();
+        let display = format!("examples/{}{}.nb", directory, name);
+        let (output, failed) = run(Path::new(&display), &source, &input);
+
+        if failed != should_fail {
+            let expectation = if should_fail { "fail" } else { "succeed" };
+            failures.push(format!("{} should {}:\n{}", display, expectation, output));
+            continue;
+        }
+        let expected_path = golden.with_extension("out");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&expected_path, &output).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == output => {}
+            Ok(expected) => failures.push(format!(
+                "{} wrote\n{}\nbut expected\n{}",
+                display, output, expected
+            )),
+            Err(_) => failures.push(format!(
+                "{} has no expected output in {}",
+                display,
+                expected_path.display()
+            )),
+        }
+    }
+}
+
+#[test]
+fn examples_write_the_expected_output() {
+    let mut failures = Vec::new();
+    check_directory("", false, &mut failures);
+    check_directory("feil/", true, &mut failures);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn the_sandbox_stops_file_access() {
+    let source = "skriv \"hei\" til filen \"ut.txt\".";
+    let program = compile(source).unwrap();
+    let mut console = Capture::new();
+    let options = Options {
+        sandbox: true,
+        ..Options::default()
+    };
+    assert!(program.run(options, &mut console).is_err());
+    assert_eq!(console.file("ut.txt"), None);
+
+    program.run(Options::default(), &mut console).unwrap();
+    assert_eq!(console.file("ut.txt"), Some("hei"));
+}
+
+#[test]
+fn assertions_can_be_skipped() {
+    let source = "påstå 1 er lik 2.\nsi \"ferdig\".";
+    let program = compile(source).unwrap();
+    assert!(program
+        .run(Options::default(), &mut Capture::new())
+        .is_err());
+
+    let mut console = Capture::new();
+    let options = Options {
+        skip_assertions: true,
+        ..Options::default()
+    };
+    program.run(options, &mut console).unwrap();
+    assert_eq!(console.output(), "ferdig\n");
+}
+
+#[test]
+fn division_by_zero_stops_the_program_at_its_line() {
+    for division in [
+        "a delt på b",
+        "kvotienten av a delt på b",
+        "rest av a delt på b",
+    ] {
+        let source = format!("la a være 7.\nla b være a minus 7.\nsi {}.", division);
+        let program = compile(&source).unwrap();
+        let mut console = Capture::new();
+        let error = program.run(Options::default(), &mut console).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "kjøretidsfeil: deling på null på linje 3"
+        );
+        assert_eq!(console.output(), "");
+    }
+}
+
+#[test]
+fn overflowing_arithmetic_gives_big_integers() {
+    let source = "la stort være 9223372036854775807.\n\
+                  si stort pluss 1.\n\
+                  si minus stort minus 2.\n\
+                  si stort ganger stort.\n\
+                  si stort pluss 1 minus 1 er lik stort.";
+    let program = compile(source).unwrap();
+    let mut console = Capture::new();
+    program.run(Options::default(), &mut console).unwrap();
+    assert_eq!(
+        console.output(),
+        "9223372036854775808\n\
+         -9223372036854775809\n\
+         85070591730234615847396907784232501249\n\
+         sant\n"
+    );
+}
+
+#[test]
+fn every_tens_word_reads_as_a_number_alone_and_with_units() {
+    let tens = [
+        ("tjue", 20),
+        ("tyve", 20),
+        ("tretti", 30),
+        ("tredve", 30),
+        ("førti", 40),
+        ("femti", 50),
+        ("seksti", 60),
+        ("sytti", 70),
+        ("åtti", 80),
+        ("nitti", 90),
+    ];
+    let units = [
+        ("en", 1),
+        ("to", 2),
+        ("tre", 3),
+        ("fire", 4),
+        ("fem", 5),
+        ("seks", 6),
+        ("sju", 7),
+        ("syv", 7),
+        ("åtte", 8),
+        ("ni", 9),
+    ];
+    let mut source = String::new();
+    let mut expected = String::new();
+    for (ten, ten_value) in tens {
+        source += &format!("si {}.\nsi liste med {} og en.\n", ten, ten);
+        expected += &format!("{}\n[{}, 1]\n", ten_value, ten_value);
+        for (unit, unit_value) in units {
+            source += &format!("si {}{}.\nsi {} {}.\n", ten, unit, ten, unit);
+            expected += &format!("{}\n{}\n", ten_value + unit_value, ten_value + unit_value);
+        }
+    }
+    let program = compile(&source).unwrap();
+    let mut console = Capture::new();
+    program.run(Options::default(), &mut console).unwrap();
+    assert_eq!(console.output(), expected);
+}
+
+#[test]
+fn exact_division_prints_fractions_or_decimals() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = fs::read_to_string(root.join("examples/brøk.nb")).unwrap();
+    let program = compile(&source).unwrap();
+    let run = |division| {
+        let mut console = Capture::new();
+        let options = Options {
+            division,
+            ..Options::default()
+        };
+        program.run(options, &mut console).unwrap();
+        console.output().to_string()
+    };
+    assert_eq!(
+        run(Division::Exact),
+        "1\n1/3\n1/2\n-1/2\n2\nsant\nsant\n1\n1/3\n1/8\n1/4\n\
+         0.7071067811865476\n0.75\n[1/2, 3]\n"
+    );
+    assert_eq!(
+        run(Division::ExactShownAsDecimal),
+        "1\n0.3333333333333333\n0.5\n-0.5\n2\nsant\nsant\n1\n0.3333333333333333\n\
+         0.125\n0.25\n0.7071067811865476\n0.75\n[0.5, 3]\n"
+    );
+}